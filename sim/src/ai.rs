@@ -0,0 +1,424 @@
+use crate::entities::{Entities, Entity, Actor};
+use crate::error::{SimError, SimResult};
+use crate::jobs::Jobs;
+use crate::map::{Map, Pos};
+use crate::map::pathservice::PathService;
+use crate::needs::{self, Need};
+use crate::rng::Rng;
+use crate::tiles::TileRegistry;
+use std::collections::HashMap;
+
+const DECIDE_INTERVAL: u32 = 500; // ms between actors rethinking what they're doing
+const WANDER_RADIUS: i32 = 6;     // How far from where it went idle an actor strolls
+const WANDER_TRIES: usize = 5;    // Random spots tried per decision before giving up
+const WANDER_PAUSE: u32 = 3000;   // ms a wanderer stands around before picking somewhere else
+const FLEE_RADIUS: usize = 8;     // Threats closer than this (in steps) are dangerous
+const FLEE_STEPS: usize = 20;     // How far an actor looks for somewhere safe
+const RNG_STREAM: u32 = 0x9e37_79b9; // Mixed into the world seed, so wandering doesn't follow other random rolls
+
+/// Behaviours colonists start with. Every behaviour scores its weight times each of its
+/// considerations, and the actor does the best scoring one it can
+pub const STANDARD: &str = "
+# name weight, then indented considerations: input curve args
+# Inputs: hunger rest joy health are 0...1, jobs is 1 if there is work to do, broken is 1 if
+# the actor won't work, danger is 1 next to a threat falling to 0 out of its reach
+# Curves: linear m b is m*x + b, below t [hold] is 1 under t (or under hold while already
+# doing it) else 0, above t is 1 over t else 0
+wander 0.1
+work 0.4
+  jobs above 0.5
+  broken below 0.5
+eat 1.0
+  hunger below 0.3 0.95
+  hunger linear -0.5 1
+sleep 1.0
+  rest below 0.3 0.95
+  rest linear -0.5 1
+relax 0.9
+  joy below 0.3 0.95
+  joy linear -0.5 1
+flee 2.0
+  danger above 0
+";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Behaviour {
+  Work,
+  Satisfy(Need),
+  Flee,
+  Wander,
+}
+
+pub const BEHAVIOURS: [Behaviour; 6] = [
+  Behaviour::Work, Behaviour::Satisfy(Need::Hunger), Behaviour::Satisfy(Need::Rest),
+  Behaviour::Satisfy(Need::Joy), Behaviour::Flee, Behaviour::Wander,
+];
+
+impl Behaviour {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Behaviour::Work => "work",
+      Behaviour::Satisfy(Need::Hunger) => "eat",
+      Behaviour::Satisfy(Need::Rest) => "sleep",
+      Behaviour::Satisfy(Need::Joy) => "relax",
+      Behaviour::Flee => "flee",
+      Behaviour::Wander => "wander",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Behaviour> {
+    BEHAVIOURS.iter().cloned().find(|b| b.name() == name)
+  }
+
+  pub fn verb(&self) -> &'static str {
+    match self {
+      Behaviour::Work => "working",
+      Behaviour::Satisfy(n) => n.verb(),
+      Behaviour::Flee => "fleeing",
+      Behaviour::Wander => "wandering",
+    }
+  }
+}
+
+/// Something about an actor or its surroundings, as 0...1
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Input {
+  Need(Need),
+  Health,
+  Jobs,
+  Broken,
+  Danger,
+}
+
+impl Input {
+  pub fn from_name(name: &str) -> Option<Input> {
+    match name {
+      "health" => Some(Input::Health),
+      "jobs" => Some(Input::Jobs),
+      "broken" => Some(Input::Broken),
+      "danger" => Some(Input::Danger),
+      n => Need::from_name(n).map(Input::Need),
+    }
+  }
+}
+
+/// Turns an input into a score
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Curve {
+  Linear(f32, f32),
+  Below(f32, f32), // Threshold to start, threshold to keep going
+  Above(f32),
+}
+
+impl Curve {
+  pub fn apply(&self, x: f32, doing: bool) -> f32 {
+    match *self {
+      Curve::Linear(m, b) => ((m * x) + b).clamp(0.0, 1.0),
+      Curve::Below(t, hold) => if x < t || (doing && x < hold) { 1.0 } else { 0.0 },
+      Curve::Above(t) => if x > t { 1.0 } else { 0.0 },
+    }
+  }
+
+  fn parse(name: &str, args: &[f32]) -> Option<Curve> {
+    match (name, args) {
+      ("linear", [m, b]) => Some(Curve::Linear(*m, *b)),
+      ("below", [t]) => Some(Curve::Below(*t, *t)),
+      ("below", [t, hold]) => Some(Curve::Below(*t, *hold)),
+      ("above", [t]) => Some(Curve::Above(*t)),
+      _ => None,
+    }
+  }
+}
+
+pub struct BehaviourDef {
+  pub behaviour: Behaviour,
+  pub weight: f32,
+  pub considerations: Vec<(Input, Curve)>,
+}
+
+/// What an actor knows when it decides, besides its own needs and health
+pub struct Senses {
+  pub jobs: bool,  // There is a job it has, or could claim
+  pub danger: f32,
+}
+
+/// Utility scoring over data-defined behaviours. Every DECIDE_INTERVAL each actor that isn't
+/// following the player's orders scores its behaviours and switches if something beats what it's
+/// doing. Idle actors with nothing better to do wander
+pub struct Brain {
+  defs: Vec<BehaviourDef>,
+  timer: u32,
+  idle: HashMap<u64, ((i32, i32), u32)>, // Wanderers: where they went idle, ms until they move on
+  rng: Rng,
+}
+
+/// The parts of the world a decision looks at, and the ones starting a behaviour changes
+pub struct Colony<'a> {
+  pub entities: &'a mut Entities,
+  pub jobs: &'a mut Jobs,
+  pub tiles: &'a TileRegistry,
+  pub map: &'a Map,
+  pub paths: &'a mut PathService,
+  pub threats: &'a [(i32, i32)],
+}
+
+impl Brain {
+  pub fn standard() -> Self {
+    Brain::parse(STANDARD).expect("standard behaviours")
+  }
+
+  /// Read behaviours in the STANDARD format
+  pub fn parse(text: &str) -> SimResult<Self> {
+    let mut defs: Vec<BehaviourDef> = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+      let bad = |msg: &str| SimError::BadDefinition(format!("line {}: {}", n + 1, msg));
+      let tokens: Vec<&str> = line.split_whitespace().collect();
+      if tokens.is_empty() || tokens[0].starts_with('#') { continue; }
+      if line.starts_with(char::is_whitespace) {
+        let def = defs.last_mut().ok_or_else(|| bad("consideration before any behaviour"))?;
+        let input = Input::from_name(tokens[0]).ok_or_else(|| bad("unknown input"))?;
+        let args = tokens[2.min(tokens.len())..].iter().map(|t| t.parse::<f32>().ok()).collect::<Option<Vec<f32>>>();
+        let curve = tokens.get(1).and_then(|c| args.and_then(|a| Curve::parse(c, &a))).ok_or_else(|| bad("bad curve"))?;
+        def.considerations.push((input, curve));
+      } else {
+        let behaviour = Behaviour::from_name(tokens[0]).ok_or_else(|| bad("unknown behaviour"))?;
+        let weight = tokens.get(1).and_then(|w| w.parse::<f32>().ok()).ok_or_else(|| bad("bad weight"))?;
+        defs.push(BehaviourDef { behaviour, weight, considerations: Vec::new() });
+      }
+    }
+    Ok(Brain { defs, timer: 0, idle: HashMap::new(), rng: Rng::new(RNG_STREAM) })
+  }
+
+  /// Start the random rolls from a world's seed, so every map wanders its own way
+  pub fn setseed(&mut self, seed: u32) {
+    self.rng = Rng::new(seed ^ RNG_STREAM);
+  }
+
+  pub fn getdefs(&self) -> &Vec<BehaviourDef> { &self.defs }
+
+  /// One line of a save file: decision timer, random state, then each wanderer as id:x,y:ms
+  pub fn serialize(&self) -> String {
+    let mut s = format!("brain {} {}", self.timer, self.rng.getstate());
+    let mut idle: Vec<_> = self.idle.iter().collect();
+    idle.sort_by_key(|(id, _)| **id);
+    for (id, ((x, y), ms)) in idle {
+      s.push_str(&format!(" {}:{},{}:{}", id, x, y, ms));
+    }
+    s
+  }
+
+  /// Carry on from a saved line. The behaviours are left as they are, they aren't saved
+  pub fn restore(&mut self, tokens: &[&str]) -> Option<()> {
+    self.timer = tokens.first()?.parse::<u32>().ok()?;
+    self.rng = Rng::new(tokens.get(1)?.parse::<u32>().ok()?);
+    self.idle.clear();
+    for t in tokens[2..].iter() {
+      let mut f = t.split([':', ',']);
+      let id = f.next()?.parse::<u64>().ok()?;
+      let x = f.next()?.parse::<i32>().ok()?;
+      let y = f.next()?.parse::<i32>().ok()?;
+      let ms = f.next()?.parse::<u32>().ok()?;
+      self.idle.insert(id, ((x, y), ms));
+    }
+    Some(())
+  }
+
+  /// Behaviours scoring above zero, best first. `current` wins ties and gets its hold thresholds
+  pub fn score(&self, a: &Actor, senses: &Senses, current: Option<Behaviour>) -> Vec<(Behaviour, f32)> {
+    let mut scores: Vec<(Behaviour, f32)> = self.defs.iter().map(|d| {
+      let doing = current == Some(d.behaviour);
+      let s = d.considerations.iter().fold(d.weight, |s, (input, curve)| {
+        let x = match input {
+          Input::Need(n) => a.needs.get(*n),
+          Input::Health => a.health,
+          Input::Jobs => if senses.jobs { 1.0 } else { 0.0 },
+          Input::Broken => if a.needs.isbroken() { 1.0 } else { 0.0 },
+          Input::Danger => senses.danger,
+        };
+        s * curve.apply(x, doing)
+      });
+      (d.behaviour, s)
+    }).filter(|(_, s)| *s > 0.0).collect();
+    scores.sort_by(|x, y| {
+      y.1.partial_cmp(&x.1).unwrap().then((current != Some(x.0)).cmp(&(current != Some(y.0))))
+    });
+    scores
+  }
+
+  pub fn update(&mut self, deltaT: u32, c: &mut Colony) {
+    self.timer = self.timer + deltaT;
+    if self.timer < DECIDE_INTERVAL { return; }
+    let elapsed = self.timer;
+    self.timer = 0;
+    let open = c.jobs.anyopen(c.map);
+    for i in 0..c.entities.getactors().len() {
+      let a = &c.entities.getactors()[i];
+      let id = a.getentityid();
+      let free = !a.ismoving() && !a.ispending();
+      if free {
+        if let Some((_, w)) = self.idle.get_mut(&id) { *w = w.saturating_sub(elapsed); }
+      }
+      // Still doing what it was? Seeing to a need always counts, it may have collapsed
+      let current = match (a.getactivity(), a.getbehaviour()) {
+        (Some((n, _)), _) => Some(Behaviour::Satisfy(n)),
+        (None, Some(Behaviour::Satisfy(_))) => None,
+        (None, Some(Behaviour::Work)) if c.jobs.job_for(id).is_none() => None,
+        (None, Some(Behaviour::Flee)) if free => None,
+        (None, Some(Behaviour::Wander)) if free && self.idle.get(&id).is_none_or(|(_, w)| *w == 0) => None,
+        (None, None) if !free => { continue; } // Following the player's orders
+        (None, b) => b,
+      };
+      let senses = Senses {
+        jobs: open || c.jobs.job_for(id).is_some(),
+        danger: danger_at(a.getoccupiedtile(), c.threats),
+      };
+      let mut now = current;
+      for (b, _) in self.score(a, &senses, current) {
+        if Some(b) == current { break; }
+        if self.start(b, i, c) {
+          now = Some(b);
+          break;
+        }
+      }
+      c.entities.getactors_mut()[i].setbehaviour(now);
+    }
+  }
+
+  /// Drop whatever actor `i` was doing and start on `b`. False, leaving it be, if it can't.
+  /// Paths come from the path service, except for fleeing: that has to start now, and only looks
+  /// FLEE_STEPS out
+  fn start(&mut self, b: Behaviour, i: usize, c: &mut Colony) -> bool {
+    let (entities, map) = (&*c.entities, c.map);
+    let a = &entities.getactors()[i];
+    let id = a.getentityid();
+    let here = Pos::from(a.getoccupiedtile());
+    let (target, path) = match b {
+      Behaviour::Work => (here.clone(), None),
+      Behaviour::Satisfy(n) => {
+        let taken: Vec<(i32, i32)> = entities.getactors().iter().enumerate()
+          .filter(|(j, _)| *j != i)
+          .filter_map(|(_, a)| a.getactivity().map(|(_, t)| t))
+          .collect();
+        match needs::find_spot(a, n, entities, &taken, c.tiles, map) { Some(t) => (Pos::from(t), None), None => { return false; } }
+      }
+      Behaviour::Flee => {
+        match map.path_aside(&here, |_| false, |p| danger_at((p.0, p.1), c.threats) > 0.0, FLEE_STEPS) {
+          Some(p) => (p.last().cloned().unwrap_or(here.clone()), Some(p)),
+          None => { return false; }
+        }
+      }
+      Behaviour::Wander => {
+        // Keeps strolling around the same spot rather than drifting off across the map
+        let anchor = match (a.getbehaviour(), self.idle.get(&id)) {
+          (Some(Behaviour::Wander), Some((p, _))) => *p,
+          _ => (here.0, here.1),
+        };
+        self.idle.insert(id, (anchor, WANDER_PAUSE));
+        match self.wander_target(&here, anchor, map) { Some(t) => (t, None), None => { return false; } }
+      }
+    };
+
+    let a = &mut c.entities.getactors_mut()[i];
+    if b == Behaviour::Work {
+      if !c.jobs.claim(a, map, c.paths) { return false; } // Can't have a job already, or it would be working
+    } else {
+      c.jobs.release_actor(id);
+      a.stop();
+      match path {
+        Some(p) if target != here => { a.setpath(p); }
+        None if target != here => { a.setmovetarget(target.0, target.1, map, c.paths); }
+        _ => {}
+      }
+    }
+    a.setactivity(match b {
+      Behaviour::Satisfy(n) => Some((n, (target.0, target.1))),
+      _ => None,
+    });
+    true
+  }
+
+  /// A random tile near `anchor` that can be reached from `from`
+  fn wander_target(&mut self, from: &Pos, anchor: (i32, i32), map: &Map) -> Option<Pos> {
+    for _ in 0..WANDER_TRIES {
+      let to = Pos(anchor.0 + self.rng.range(WANDER_RADIUS), anchor.1 + self.rng.range(WANDER_RADIUS));
+      if to != *from && map.ispassable(to.0, to.1) && map.is_reachable(from, &to) { return Some(to); }
+    }
+    None
+  }
+}
+
+/// 1 on a threat, falling to 0 at FLEE_RADIUS steps from the nearest one
+fn danger_at(p: (i32, i32), threats: &[(i32, i32)]) -> f32 {
+  let d = threats.iter().map(|t| Pos::from(*t).distance(&Pos::from(p))).min();
+  match d {
+    Some(d) if d < FLEE_RADIUS => 1.0 - (d as f32 / FLEE_RADIUS as f32),
+    _ => 0.0,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::map::test_map;
+  use crate::needs::NEEDS;
+
+  #[test]
+  fn test_parse_and_score() {
+    let brain = Brain::standard();
+    assert_eq!(brain.getdefs().len(), BEHAVIOURS.len());
+    assert!(Brain::parse("wander 0.1\n  hunger sideways 2").is_err());
+    assert!(Brain::parse("  hunger below 0.3").is_err());
+    assert!(Brain::parse("dance 1.0").is_err());
+
+    let mut a = Actor::new(0, 0, 0, 1.0);
+    let senses = Senses { jobs: true, danger: 0.0 };
+    assert_eq!(brain.score(&a, &senses, None)[0].0, Behaviour::Work);
+    a.needs.set(Need::Hunger, 0.2);
+    assert_eq!(brain.score(&a, &senses, None)[0].0, Behaviour::Satisfy(Need::Hunger));
+    // Half fed: only worth carrying on with once started
+    a.needs.set(Need::Hunger, 0.6);
+    assert_eq!(brain.score(&a, &senses, None)[0].0, Behaviour::Work);
+    assert_eq!(brain.score(&a, &senses, Some(Behaviour::Satisfy(Need::Hunger)))[0].0, Behaviour::Satisfy(Need::Hunger));
+    assert_eq!(brain.score(&a, &Senses { jobs: true, danger: 0.5 }, None)[0].0, Behaviour::Flee);
+    assert!(NEEDS.iter().all(|n| Behaviour::from_name(Behaviour::Satisfy(*n).name()).is_some()));
+  }
+
+  #[test]
+  fn test_wander_and_flee() {
+    let tiles = TileRegistry::standard();
+    let map = test_map(30, 30);
+    let mut entities = Entities::new();
+    let mut jobs = Jobs::new();
+    let mut brain = Brain::standard();
+    let mut paths = PathService::new(1);
+    entities.add_actor(Actor::new(0, 15, 15, 1.0));
+    let mut other = Brain::standard();
+    other.setseed(1);
+    assert_ne!(other.serialize(), brain.serialize());
+
+    // Nothing to do: it doesn't just stand there
+    let mut visited = Vec::new();
+    for _ in 0..2000 {
+      entities.collect_paths(paths.wait(), &mut paths, &map);
+      entities.update(16, 64.0, &map);
+      brain.update(16, &mut Colony { entities: &mut entities, jobs: &mut jobs, tiles: &tiles, map: &map, paths: &mut paths, threats: &[] });
+      let t = entities.getactors()[0].getoccupiedtile();
+      if !visited.contains(&t) { visited.push(t); }
+    }
+    assert_eq!(entities.getactors()[0].getbehaviour(), Some(Behaviour::Wander));
+    assert!(visited.len() > 3);
+
+    // Something dangerous turns up next to it
+    let here = entities.getactors()[0].getoccupiedtile();
+    let threats = [(here.0 + 1, here.1)];
+    let mut fled = false;
+    for _ in 0..600 {
+      entities.collect_paths(paths.wait(), &mut paths, &map);
+      entities.update(16, 64.0, &map);
+      brain.update(16, &mut Colony { entities: &mut entities, jobs: &mut jobs, tiles: &tiles, map: &map, paths: &mut paths, threats: &threats });
+      let a = &entities.getactors()[0];
+      fled = fled || (a.getbehaviour() == Some(Behaviour::Flee) && danger_at(a.getoccupiedtile(), &threats) == 0.0);
+    }
+    assert!(fled);
+  }
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+pub const TICK_MS: u32 = 16;          // Length of one simulation tick
+const MAX_FRAME_MS: u32 = 250;        // Longer frames are cut short so a hitch can't snowball into more and more ticks
+pub const MAX_SPEED: u32 = 3;
+
+/// Fixed timestep accumulator. Frames put real time in, the simulation takes it out in whole
+/// ticks, and whatever is left over says how far to blend drawing towards the next tick
+pub struct Clock {
+  accumulator: u32,
+  speed: u32,   // Ticks per TICK_MS of real time, 0 is paused
+  resume: u32,  // Speed to go back to when unpausing
+  stepping: u32, // Single ticks asked for while paused
+}
+
+impl Default for Clock {
+  fn default() -> Self { Clock::new() }
+}
+
+impl Clock {
+  pub fn new() -> Self {
+    Clock { accumulator: 0, speed: 1, resume: 1, stepping: 0 }
+  }
+
+  /// Add a frame's worth of time and return how many ticks to run
+  pub fn advance(&mut self, frame: Duration) -> u32 {
+    if self.speed == 0 {
+      let ticks = self.stepping;
+      self.stepping = 0;
+      return ticks;
+    }
+    let ms = (frame.as_secs() as u32).saturating_mul(1000).saturating_add(frame.subsec_millis());
+    self.accumulator = self.accumulator + (ms.min(MAX_FRAME_MS) * self.speed);
+    let ticks = self.accumulator / TICK_MS;
+    self.accumulator = self.accumulator % TICK_MS;
+    ticks
+  }
+
+  /// How far between the last tick and the next one we are, 0...1
+  pub fn alpha(&self) -> f32 {
+    if self.speed == 0 { return 1.0; } // Paused: show the last tick as it is
+    self.accumulator as f32 / TICK_MS as f32
+  }
+
+  pub fn getspeed(&self) -> u32 { self.speed }
+
+  /// 0 pauses, anything above MAX_SPEED is capped
+  pub fn setspeed(&mut self, speed: u32) {
+    self.speed = speed.min(MAX_SPEED);
+    if self.speed > 0 { self.resume = self.speed; }
+  }
+
+  pub fn toggle_pause(&mut self) {
+    if self.speed == 0 {
+      self.speed = self.resume;
+    } else {
+      self.speed = 0;
+    }
+  }
+
+  /// Run exactly one tick on the next advance. Only does anything while paused
+  pub fn step(&mut self) {
+    if self.speed == 0 { self.stepping = self.stepping + 1; }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::entities::{Entities, Entity, Actor, Wall};
+  use crate::items::Stockpiles;
+  use crate::jobs::Jobs;
+  use crate::map::test_map;
+  use crate::tiles::TileRegistry;
+  use crate::world::World;
+
+  /// Send two actors across the map and wall one of them off on the way, with frames of
+  /// `frame_ms`, for `total_ms`. Paths come from the path service like any other order, waited for
+  /// at the start of the next tick
+  fn run(frame_ms: u64, total_ms: u64) -> Vec<(f32, f32)> {
+    let mut e = Entities::new();
+    e.add_actor(Actor::new(0, 1, 1, 1.0));
+    e.add_actor(Actor::new(0, 1, 20, 1.0));
+    let mut world = World::from_parts(TileRegistry::standard(), test_map(30, 30), e, Jobs::new(), Stockpiles::new());
+    world.waitforpaths = true; // Otherwise when a path turns up depends on the workers, not the frames
+    world.entities.get_actor_by_id(0).unwrap().setmovetarget(25, 9, &world.map, &mut world.paths);
+    let mut clock = Clock::new();
+    let mut ticks = 0;
+    for _ in 0..(total_ms / frame_ms) {
+      for _ in 0..clock.advance(Duration::from_millis(frame_ms)) {
+        if ticks == 10 {
+          world.entities.get_actor_by_id(1).unwrap().setmovetarget(25, 20, &world.map, &mut world.paths);
+        }
+        if ticks == 30 {
+          let w = Wall::new(0, 12, 20, 1.0, world.entities.getindex());
+          world.map.set_building_at(12, 20, w, &mut world.entities).unwrap();
+        }
+        world.tick().unwrap();
+        ticks = ticks + 1;
+      }
+    }
+    world.entities.getactors().iter().map(|a| a.getposition()).collect()
+  }
+
+  #[test]
+  fn test_same_at_any_frame_rate() {
+    // A little over a second at about 30, 60 and 240 FPS, and at a choppy 10 FPS
+    let a = run(32, 1152);
+    assert_eq!(a, run(16, 1152));
+    assert_eq!(a, run(4, 1152));
+    assert_eq!(a, run(96, 1152));
+    assert_ne!(a[0], (64.0, 64.0));
+    assert_ne!(a[1], (64.0, 1280.0));
+  }
+
+  #[test]
+  fn test_speed_controls() {
+    let frame = Duration::from_millis(TICK_MS as u64 * 2);
+    let mut clock = Clock::new();
+    assert_eq!(clock.advance(frame), 2);
+    clock.setspeed(3);
+    assert_eq!(clock.advance(frame), 6);
+
+    clock.toggle_pause();
+    assert_eq!(clock.advance(frame), 0);
+    clock.step();
+    assert_eq!(clock.advance(frame), 1);
+    assert_eq!(clock.advance(frame), 0);
+
+    clock.toggle_pause();
+    assert_eq!(clock.getspeed(), 3);
+    clock.step(); // Ignored while running
+    assert_eq!(clock.advance(frame), 6);
+  }
+}
@@ -0,0 +1,956 @@
+use crate::TILESIZE;
+use crate::map::{Pos, Map};
+use crate::map::pathservice::{PathService, PathResult};
+use crate::map::flowfield::FlowField;
+use crate::needs::{Need, Needs, NEEDS};
+use crate::ai::Behaviour;
+use crate::items::{ItemKind, Stack};
+use std::collections::HashMap;
+use bresenham::Bresenham;
+
+pub trait BuildableEntity: Buildable + Entity {}
+
+pub trait Entity {
+  fn getoccupiedtile(&self) -> (i32, i32); // Get tile position
+  fn getid(&self) -> u32;
+  fn getposition(&self) -> (f32, f32); // Get map position (where map position = tile position * TILESIZE)
+  fn getrotation(&self) -> f32;
+}
+
+pub struct Tile {
+  pub id: u32,
+  pub alt: u32,
+  pub scrx: f32,
+  pub scry: f32,
+  x: i32,
+  y: i32,
+  pub movecost: usize,  // dijkstra weight
+  pub edges: u8,  // EDGE_* flags for sides that border lower terrain
+}
+
+impl Tile {
+  pub fn new(id: u32, x: i32, y: i32, s: f32) -> Self { 
+    Tile { id, alt: 0, x, y,
+      scrx: (TILESIZE * x) as f32 * s,
+      scry: (TILESIZE * y) as f32 * s,
+      movecost: 1,
+      edges: 0,
+    } }
+  pub fn setalternate(&mut self, alt: &u32) { self.alt = *alt; }
+  pub fn setedges(&mut self, edges: u8) { self.edges = edges; }
+  pub fn getedges(&self) -> u8 { self.edges }
+  pub fn setmovecost(&mut self, cost: usize) { self.movecost = cost; }
+  pub fn getmovecost(&self) -> usize { self.movecost }
+}
+
+impl Entity for Tile {
+  fn getoccupiedtile(&self) -> (i32, i32) { (self.x, self.y) }
+  fn getid(&self) -> u32 { self.id }
+  fn getposition(&self) -> (f32, f32) { (self.scrx, self.scry) }
+  fn getrotation(&self) -> f32 { 0.0 }
+}
+
+pub trait Buildable {
+  fn setentityid(&mut self, i: u64);
+  fn getentityid(&self) -> u64;
+  fn getmovecost(&self) -> usize;
+  fn serialize(&self) -> String; // One line of a save file, starting with the building kind
+  fn isblueprint(&self) -> bool { false }
+  fn setprogress(&mut self, _p: f32) {}
+  fn getprogress(&self) -> f32 { 1.0 } // How far along construction is, 0...1
+  fn iscrossable(&self) -> bool { self.getmovecost() < usize::MAX } // False blocks pathing whatever the cost
+  fn islockable(&self) -> bool { false }
+  fn islocked(&self) -> bool { false }
+  fn setlocked(&mut self, _l: bool) {}
+  fn setopen(&mut self, _o: bool) {}  // An actor is in the doorway
+  fn getalternate(&self) -> Option<usize> { None } // Building alternate sprite to draw instead, if any
+  fn getservice(&self) -> Option<Need> { None }     // Need an actor can see to by standing on it
+  fn getmaterials(&self) -> Option<Stack> { None }   // What went into building it
+  fn gethitpoints(&self) -> u32 { 0 }                // How much knocking down it takes
+}
+
+impl PartialEq for dyn Buildable {
+  fn eq(&self, other: &Self) -> bool {
+    self.getentityid() == other.getentityid()
+  }
+}
+  
+// Building sprite ids. The game finds each one's image by its name in BUILDING_NAMES
+pub const WALL_SPRITE: u32 = 0;
+pub const STONE_WALL_SPRITE: u32 = 1;
+pub const DOOR_SPRITE: u32 = 2;
+pub const BED_SPRITE: u32 = 3;
+pub const TABLE_SPRITE: u32 = 4;
+
+pub const BUILDING_NAMES: [&str; 5] = ["wall0", "stonewall0", "door0", "bed0", "table0"];
+
+// Items each kind of building takes to put up
+pub const WALL_MATERIALS: u32 = 10;     // Of wood or stone, whichever the wall is made of
+pub const DOOR_MATERIALS: u32 = 8;      // Of wood
+pub const FURNITURE_MATERIALS: u32 = 12; // Of wood
+
+const WOOD_WALL_HP: u32 = 100;
+const STONE_WALL_HP: u32 = 300;
+const DOOR_HP: u32 = 80;
+const FURNITURE_HP: u32 = 50;
+
+#[derive(Copy, Clone)]
+pub struct Wall {
+  pub id: u32,
+  pub scrx: f32,
+  pub scry: f32,
+  pub rotation: f32,
+  x: i32,
+  y: i32,
+  pub crossable: bool,  // so we can use this for short barriers, doors, etc
+  pub movecost: usize,  // dijkstra weight
+  pub material: ItemKind,
+  pub hitpoints: u32,
+  entityid: u64,
+}
+
+impl Wall {
+  /// The sprite says what the wall is made of: STONE_WALL_SPRITE for stone, anything else wood
+  pub fn new(id: u32, x: i32, y: i32, s: f32, e: u64) -> Self {
+    let (material, hitpoints) = match id {
+      STONE_WALL_SPRITE => (ItemKind::Stone, STONE_WALL_HP),
+      _ => (ItemKind::Wood, WOOD_WALL_HP),
+    };
+    Wall {id, x, y,
+      scrx: (TILESIZE * x) as f32 * s,
+      scry: (TILESIZE * y) as f32 * s,
+      rotation: 0.0,
+      crossable: false,
+      movecost: usize::MAX,
+      material,
+      hitpoints,
+      entityid: e,
+    }
+  }
+}
+
+impl Buildable for Wall {
+  fn setentityid(&mut self, i: u64) { self.entityid = i; }
+  fn getentityid(&self) -> u64 { self.entityid }
+  fn getmovecost(&self) -> usize { self.movecost }
+  fn serialize(&self) -> String { format!("wall {} {} {} {}", self.entityid, self.id, self.x, self.y) }
+  fn iscrossable(&self) -> bool { self.crossable }
+  fn getmaterials(&self) -> Option<Stack> { Some(Stack::new(self.material, WALL_MATERIALS)) }
+  fn gethitpoints(&self) -> u32 { self.hitpoints }
+}
+
+impl Buildable for &mut Wall {
+  fn getentityid(&self) -> u64 { self.entityid }
+  fn setentityid(&mut self, i: u64) { self.entityid = i; }
+  fn getmovecost(&self) -> usize { self.movecost }
+  fn serialize(&self) -> String { (**self).serialize() }
+}
+
+impl BuildableEntity for Wall {}
+
+impl Entity for Wall {
+  fn getoccupiedtile(&self) -> (i32, i32) { (self.x, self.y) }
+  fn getid(&self) -> u32 { self.id }
+  fn getposition(&self) -> (f32, f32) { (self.scrx, self.scry) }
+  fn getrotation(&self) -> f32 { self.rotation }
+}
+
+
+
+const DOOR_COST: usize = 4; // Slower than open ground so paths only use doors when it saves a detour
+
+/// Walkable like a wall with a gap in it, unless locked
+#[derive(Copy, Clone)]
+pub struct Door {
+  pub id: u32,
+  pub scrx: f32,
+  pub scry: f32,
+  x: i32,
+  y: i32,
+  pub locked: bool,
+  pub open: bool,
+  entityid: u64,
+}
+
+impl Door {
+  pub fn new(id: u32, x: i32, y: i32, s: f32, e: u64) -> Self {
+    Door {id, x, y,
+      scrx: (TILESIZE * x) as f32 * s,
+      scry: (TILESIZE * y) as f32 * s,
+      locked: false,
+      open: false,
+      entityid: e,
+    }
+  }
+}
+
+impl Buildable for Door {
+  fn setentityid(&mut self, i: u64) { self.entityid = i; }
+  fn getentityid(&self) -> u64 { self.entityid }
+  fn getmovecost(&self) -> usize { if self.locked { usize::MAX } else { DOOR_COST } }
+  fn serialize(&self) -> String { format!("door {} {} {} {} {}", self.entityid, self.id, self.x, self.y, self.locked) }
+  fn islockable(&self) -> bool { true }
+  fn islocked(&self) -> bool { self.locked }
+  fn setlocked(&mut self, l: bool) { self.locked = l; if l { self.open = false; } }
+  fn setopen(&mut self, o: bool) { self.open = o && !self.locked; }
+  fn getalternate(&self) -> Option<usize> { if self.open { Some(0) } else { None } }
+  fn getmaterials(&self) -> Option<Stack> { Some(Stack::new(ItemKind::Wood, DOOR_MATERIALS)) }
+  fn gethitpoints(&self) -> u32 { DOOR_HP }
+}
+
+impl BuildableEntity for Door {}
+
+impl Entity for Door {
+  fn getoccupiedtile(&self) -> (i32, i32) { (self.x, self.y) }
+  fn getid(&self) -> u32 { self.id }
+  fn getposition(&self) -> (f32, f32) { (self.scrx, self.scry) }
+  fn getrotation(&self) -> f32 { 0.0 }
+}
+
+const FURNITURE_COST: usize = 3; // Walkable, but paths go around it when they can
+
+/// Beds and tables. An actor standing on one sees to its need faster than making do without
+#[derive(Copy, Clone)]
+pub struct Furniture {
+  pub id: u32,
+  pub scrx: f32,
+  pub scry: f32,
+  x: i32,
+  y: i32,
+  pub service: Need,
+  entityid: u64,
+}
+
+impl Furniture {
+  pub fn new(id: u32, x: i32, y: i32, s: f32, e: u64, service: Need) -> Self {
+    Furniture {id, x, y,
+      scrx: (TILESIZE * x) as f32 * s,
+      scry: (TILESIZE * y) as f32 * s,
+      service,
+      entityid: e,
+    }
+  }
+}
+
+impl Buildable for Furniture {
+  fn setentityid(&mut self, i: u64) { self.entityid = i; }
+  fn getentityid(&self) -> u64 { self.entityid }
+  fn getmovecost(&self) -> usize { FURNITURE_COST }
+  fn serialize(&self) -> String { format!("furniture {} {} {} {} {}", self.entityid, self.id, self.x, self.y, self.service.name()) }
+  fn getservice(&self) -> Option<Need> { Some(self.service) }
+  fn getmaterials(&self) -> Option<Stack> { Some(Stack::new(ItemKind::Wood, FURNITURE_MATERIALS)) }
+  fn gethitpoints(&self) -> u32 { FURNITURE_HP }
+}
+
+impl BuildableEntity for Furniture {}
+
+impl Entity for Furniture {
+  fn getoccupiedtile(&self) -> (i32, i32) { (self.x, self.y) }
+  fn getid(&self) -> u32 { self.id }
+  fn getposition(&self) -> (f32, f32) { (self.scrx, self.scry) }
+  fn getrotation(&self) -> f32 { 0.0 }
+}
+
+/// A planned building. Drawn see-through and walkable until its materials are brought and a
+/// colonist finishes building it
+#[derive(Copy, Clone)]
+pub struct Blueprint {
+  pub id: u32,  // Sprite of the finished building
+  pub scrx: f32,
+  pub scry: f32,
+  x: i32,
+  y: i32,
+  pub progress: f32, // 0...1
+  entityid: u64,
+}
+
+impl Blueprint {
+  pub fn new(id: u32, x: i32, y: i32, s: f32, e: u64) -> Self {
+    Blueprint {id, x, y,
+      scrx: (TILESIZE * x) as f32 * s,
+      scry: (TILESIZE * y) as f32 * s,
+      progress: 0.0,
+      entityid: e,
+    }
+  }
+}
+
+impl Buildable for Blueprint {
+  fn setentityid(&mut self, i: u64) { self.entityid = i; }
+  fn getentityid(&self) -> u64 { self.entityid }
+  fn getmovecost(&self) -> usize { 0 } // Never more than the terrain under it
+  fn serialize(&self) -> String { format!("blueprint {} {} {} {} {}", self.entityid, self.id, self.x, self.y, self.progress) }
+  fn isblueprint(&self) -> bool { true }
+  fn setprogress(&mut self, p: f32) { self.progress = p; }
+  fn getprogress(&self) -> f32 { self.progress }
+}
+
+impl BuildableEntity for Blueprint {}
+
+impl Entity for Blueprint {
+  fn getoccupiedtile(&self) -> (i32, i32) { (self.x, self.y) }
+  fn getid(&self) -> u32 { self.id }
+  fn getposition(&self) -> (f32, f32) { (self.scrx, self.scry) }
+  fn getrotation(&self) -> f32 { 0.0 }
+}
+
+pub struct Actor {
+  id: u32,
+  scrx: f32,
+  scry: f32,
+  x: i32,
+  y: i32,
+  pub speed: f32,
+  moving: bool,
+  steps: Vec::<(i32, i32)>,
+  entityid: u64,
+  pub selected: bool,
+  prevx: f32,           // Position at the start of the last tick, drawing blends from here to scrx/scry
+  prevy: f32,
+  pending: Option<(u64, (i32, i32), bool)>, // Ticket of the path this actor is waiting on, where to, and if only next to it
+  waited: u32,          // ms spent waiting for another actor to get out of the way
+  pub needs: Needs,
+  pub health: f32,      // 0...1, dies at 0
+  pub carrying: Option<Stack>, // Items being hauled
+  activity: Option<(Need, (i32, i32))>, // Need being seen to, and the tile it's seen to on
+  behaviour: Option<Behaviour>, // What the Brain has it doing, None while under the player's orders
+}
+
+impl Entity for Actor {
+  fn getid(&self) -> u32 { self.id }
+  fn getoccupiedtile(&self) -> (i32, i32) { (self.scrx as i32 / TILESIZE, self.scry as i32 / TILESIZE) }
+  fn getposition(&self) -> (f32, f32) { (self.scrx, self.scry) }
+  fn getrotation(&self) -> f32 { 0.0 }
+}
+
+impl Actor {
+  pub fn new(id: u32, x: i32, y: i32, _s: f32) -> Self {
+    let scrx = TILESIZE as f32 * x as f32;
+    let scry = TILESIZE as f32 * y as f32;
+    let s = 0.5;
+    let m = false;
+    let st = Vec::new();
+    Actor { id, 
+            scrx, 
+            scry,
+            prevx: scrx,
+            prevy: scry,
+            x,
+            y,
+            speed: s,
+            moving: m,
+            steps: st,
+            entityid: 0,
+            selected: false,
+            pending: None,
+            waited: 0,
+            needs: Needs::new(),
+            health: 1.0,
+            carrying: None,
+            activity: None,
+            behaviour: None }
+  }
+
+  pub fn getentityid(&self) -> u64 { self.entityid }
+
+  pub fn getactivity(&self) -> Option<(Need, (i32, i32))> { self.activity }
+  pub fn setactivity(&mut self, a: Option<(Need, (i32, i32))>) { self.activity = a; }
+
+  pub fn getbehaviour(&self) -> Option<Behaviour> { self.behaviour }
+
+  /// None hands the actor to the player, dropping any need it was seeing to
+  pub fn setbehaviour(&mut self, b: Option<Behaviour>) {
+    if b.is_none() { self.activity = None; }
+    self.behaviour = b;
+  }
+
+  /// Walking speed, slowed by needs left empty
+  pub fn getspeed(&self) -> f32 { self.speed * self.needs.workrate() }
+
+  /// Map position to draw at, `alpha` of the way from the last tick's position to this one's
+  pub fn getdrawposition(&self, alpha: f32) -> (f32, f32) {
+    (self.prevx + ((self.scrx - self.prevx) * alpha), self.prevy + ((self.scry - self.prevy) * alpha))
+  }
+
+  /// Tile the actor's sprite mostly covers, which it keeps others out of
+  pub fn gettile(&self) -> (i32, i32) {
+    let t = TILESIZE as f32;
+    (((self.scrx + (t / 2.0)) / t).floor() as i32, ((self.scry + (t / 2.0)) / t).floor() as i32)
+  }
+
+  /// True if map pixel position (x, y) is on this actor's sprite
+  pub fn contains(&self, x: f32, y: f32) -> bool {
+    x >= self.scrx && x < self.scrx + TILESIZE as f32 &&
+    y >= self.scry && y < self.scry + TILESIZE as f32
+  }
+
+  pub fn update(&mut self, deltaT: u32) {
+    if self.moving {
+        let a = self.steps.first();
+        let (x, y) = a.unwrap();
+        self.movestep(*x, *y, deltaT);
+      }
+
+  }
+
+  pub fn lineofsight_vis(&mut self, x: i32, y: i32, _map: &mut Map) -> bool {
+    for (_x, _y) in Bresenham::new((self.x as isize, self.y as isize), (x as isize, y as isize)) {
+      
+      
+
+    }
+    true
+  }
+
+  pub fn lineofsight_mov(x1: i32, y1: i32, x: i32, y: i32, map: &Map) -> bool {
+    for (x, y) in Bresenham::new((x1 as isize, y1 as isize), (x as isize, y as isize)) {
+      let (x, y) = (x as i32, y as i32);
+      if !map.check_bounds(x, y) { return false }
+      match map.costmap.get(map.getmapvecidx(x, y)) {
+        Some(&a) => { if a == usize::MAX { return false } }
+        _ => { return false }
+      }
+    }
+    true
+
+  }
+
+
+  /// Interprets grid tiles in x, y into map pixel coordinates and moves one step towards it
+  fn movestep(&mut self, x: i32, y: i32, deltaT: u32) -> bool {
+    let x = (x * TILESIZE) as f32;
+    let y = (y * TILESIZE) as f32;
+    let destx = x;
+    let desty = y;
+
+
+    let (x, y) = (-(self.scrx - x), -(self.scry - y));
+    let left = ((x * x) + (y * y)).sqrt();
+    let a = Entities::normalize_withspeed(self, x, y);
+    let (x, y) = a; 
+    
+    if self.getspeed() * deltaT as f32 >= left {
+      // Close enough to reach it this tick; don't overshoot
+      self.scrx = destx;
+      self.scry = desty;
+    } else {
+      self.scrx = self.scrx + (x * deltaT as f32);
+      self.scry = self.scry + (y * deltaT as f32);
+    }
+    if (self.scrx as i32 - destx as i32).abs() <= 2 &&
+      (self.scry as i32 - desty as i32).abs() <= 2 {
+
+      // Snap onto the waypoint, diagonal steps rarely land on whole pixels
+      self.scrx = destx;
+      self.scry = desty;
+      self.steps.remove(0);
+      if self.steps.is_empty() { self.moving = false; }
+    }
+
+    let (x,y) = self.getoccupiedtile();
+    self.x = x;
+    self.y = y;
+    true
+  }
+
+/// Set move target for actor in grid tiles (x, y). The path is found in the background and
+/// picked up by Entities::collect_paths, until then the actor keeps to its old path
+  pub fn setmovetarget(&mut self, x: i32, y: i32, map: &Map, paths: &mut PathService) -> bool {
+    if self.x == x && self.y == y { return false; }
+    let start = if self.moving { Pos(self.steps[0].0, self.steps[0].1) } else { Pos(self.x, self.y) };
+    if !map.is_reachable(&start, &Pos(x, y)) { return false; } // Other island, closed room or solid tile
+    self.pending = Some((paths.request(self.entityid, start, Pos(x, y), map), (x, y), false));
+    true
+  }
+
+  /// Like setmovetarget, but to whichever tile next to (x, y) is closest, for work done on a tile
+  /// rather than standing in it
+  pub fn setworktarget(&mut self, x: i32, y: i32, map: &Map, paths: &mut PathService) -> bool {
+    let start = if self.moving { Pos(self.steps[0].0, self.steps[0].1) } else { Pos(self.x, self.y) };
+    if start.distance(&Pos(x, y)) == 1 || !map.is_reachable_adjacent(&start, &Pos(x, y)) { return false; }
+    self.pending = Some((paths.request_adjacent(self.entityid, start, Pos(x, y), map), (x, y), true));
+    true
+  }
+
+  /// Walk to a flow field's goal, from the tile the actor is on. Returns false if it can't get there
+  pub fn follow(&mut self, field: &FlowField, map: &Map) -> bool {
+    let (x, y) = self.getoccupiedtile();
+    match field.path_from(&Pos(x, y), map) {
+      Some(path) => { self.stop(); self.setpath(path) }
+      None => false,
+    }
+  }
+
+  /// Drop the current path and ask for a new one to the same destination, from where the actor stands
+  pub fn replan(&mut self, map: &Map, paths: &mut PathService) -> bool {
+    let dest = match self.steps.last() { Some(d) => *d, None => { return false; } };
+    self.stop();
+    self.setmovetarget(dest.0, dest.1, map, paths)
+  }
+
+  /// Put the actor down on tile (x, y), dropping whatever it was doing
+  pub fn place_at(&mut self, x: i32, y: i32) {
+    self.scrx = (x * TILESIZE) as f32;
+    self.scry = (y * TILESIZE) as f32;
+    self.prevx = self.scrx;
+    self.prevy = self.scry;
+    self.x = x;
+    self.y = y;
+    self.stop();
+  }
+
+  /// True while a path for this actor is being searched for
+  pub fn ispending(&self) -> bool { self.pending.is_some() }
+
+  /// Tile the path being searched for leads to. Not for paths to work next to a tile, those
+  /// belong to a job and are asked for again when it's claimed
+  pub fn getorder(&self) -> Option<(i32, i32)> {
+    self.pending.filter(|(_, _, adjacent)| !adjacent).map(|(_, to, _)| to)
+  }
+
+  /// Follow a path from Map::getpath. Paths are 8-way, so every step is kept as a waypoint
+  pub fn setpath(&mut self, steps: Vec<Pos>) -> bool {
+    let last = match steps.last() {
+      Some(Pos(x, y)) => (*x, *y),
+      None => { return false; }
+    };
+    self.steps.clear();
+    self.moving = true;
+    let here = (self.x, self.y);
+    for Pos(x, y) in steps {
+      if (x, y) == here && self.steps.is_empty() { continue; } // Already standing on the start
+      self.steps.push((x, y));
+    }
+    if self.steps.is_empty() { self.steps.push(last); }
+    true
+  }
+
+  pub fn ismoving(&self) -> bool { self.moving }
+  pub fn getsteps(&self) -> &Vec<(i32, i32)> { &self.steps }
+
+  /// Tile the actor is currently walking towards, if any
+  pub fn nextstep(&self) -> Option<(i32, i32)> {
+    if self.moving { self.steps.first().cloned() } else { None }
+  }
+
+  /// Drop the rest of the path and settle on the current tile
+  pub fn stop(&mut self) {
+    self.pending = None;
+    self.clearmovetarget();
+  }
+
+  /// One line of a save file: sprite id, map position, speed, needs, health, then any remaining waypoints
+  pub fn serialize(&self) -> String {
+    let mut s = format!("actor {} {} {} {} {} {}", self.entityid, self.id, self.scrx, self.scry, self.speed, self.moving);
+    for n in NEEDS.iter() {
+      s.push_str(&format!(" {}", self.needs.get(*n)));
+    }
+    s.push_str(&format!(" {}", self.health));
+    for (x, y) in self.steps.iter() {
+      s.push_str(&format!(" {},{}", x, y));
+    }
+    s
+  }
+
+  pub fn deserialize(tokens: &[&str]) -> Option<Actor> {
+    if tokens.len() < 10 { return None; }
+    let entityid = tokens[0].parse::<u64>().ok()?;
+    let id = tokens[1].parse::<u32>().ok()?;
+    let scrx = tokens[2].parse::<f32>().ok()?;
+    let scry = tokens[3].parse::<f32>().ok()?;
+    let mut a = Actor::new(id, 0, 0, 1.0);
+    a.entityid = entityid;
+    a.scrx = scrx;
+    a.scry = scry;
+    a.prevx = scrx;
+    a.prevy = scry;
+    a.speed = tokens[4].parse::<f32>().ok()?;
+    a.moving = tokens[5].parse::<bool>().ok()?;
+    for (i, n) in NEEDS.iter().enumerate() {
+      a.needs.set(*n, tokens[6 + i].parse::<f32>().ok()?);
+    }
+    a.health = tokens[9].parse::<f32>().ok()?;
+    for t in tokens[10..].iter() {
+      let mut xy = t.split(',');
+      let x = xy.next()?.parse::<i32>().ok()?;
+      let y = xy.next()?.parse::<i32>().ok()?;
+      a.steps.push((x, y));
+    }
+    if a.moving && a.steps.is_empty() { a.moving = false; }
+    let (x, y) = a.getoccupiedtile();
+    a.x = x;
+    a.y = y;
+    Some(a)
+  }
+
+  pub fn clearmovetarget(&mut self) {
+    self.steps.clear();
+    self.steps.push(self.getoccupiedtile());
+    self.moving = true;
+  }
+}
+
+const WAIT_LIMIT: u32 = 1500; // ms an actor waits on a blocked tile before one side steps aside
+const ASIDE_STEPS: usize = 8;  // How far an actor will go to get out of someone's way
+
+pub struct Entities {
+  tiles: Vec<Tile>,
+  buildings: HashMap<u64, Box<dyn BuildableEntity>>,
+  actors: Vec<Actor>,
+  entityindex: u64,
+  reservations: HashMap<(i32, i32), u64>, // Destination tile -> the one actor allowed to end up there
+}
+
+impl Default for Entities {
+  fn default() -> Self { Entities::new() }
+}
+
+impl Entities {
+  pub fn new() -> Self {
+    Entities { tiles: Vec::new(), buildings: HashMap::new(), actors: Vec::new(), entityindex: 0, reservations: HashMap::new(), }
+  }
+
+  pub fn getindex(&self) -> u64 { self.entityindex }
+  pub fn setindex(&mut self, i: u64) { self.entityindex = i; }
+
+  pub fn getactors(&self) -> &Vec<Actor> { &self.actors }
+  pub fn getactors_mut(&mut self) -> &mut Vec<Actor> { &mut self.actors }
+
+  pub fn get_actor_by_id(&mut self, id: u64) -> Option<&mut Actor> {
+    self.actors.iter_mut().find(|a| a.entityid == id)
+  }
+  pub fn getbuildings(&self) -> &HashMap<u64, Box<dyn BuildableEntity>> { &self.buildings }
+
+  pub fn gettiles(&self) -> &Vec<Tile> { &self.tiles }
+
+  pub fn add_tile(&mut self, tile: Tile) {
+    self.tiles.push(tile);
+  }
+
+  pub fn add_actor(&mut self, mut act: Actor) {
+    act.entityid = self.entityindex;
+    self.entityindex = self.entityindex + 1;
+    self.actors.push(act);
+  }
+
+  /// Add an actor that already has an entity id, e.g. one read back from a save
+  pub fn insert_actor(&mut self, act: Actor) {
+    if act.entityid >= self.entityindex { self.entityindex = act.entityid + 1; }
+    self.actors.push(act);
+  }
+
+  pub fn get_selected(&mut self) -> impl Iterator<Item = &mut Actor> {
+    self.actors.iter_mut().filter(|a| a.selected)
+  }
+
+  pub fn clear_selection(&mut self) {
+    for a in self.actors.iter_mut() { a.selected = false; }
+  }
+
+  /// Select the topmost actor under map pixel position (x, y). Returns false if there was none
+  pub fn select_at(&mut self, x: f32, y: f32) -> bool {
+    self.clear_selection();
+    match self.actors.iter_mut().rev().find(|a| a.contains(x, y)) {
+      Some(a) => { a.selected = true; true }
+      None => false,
+    }
+  }
+
+  /// Select every actor whose sprite overlaps the map pixel rectangle between (x1, y1) and (x2, y2)
+  pub fn select_in(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) -> usize {
+    let (minx, maxx) = (x1.min(x2), x1.max(x2));
+    let (miny, maxy) = (y1.min(y2), y1.max(y2));
+    let t = TILESIZE as f32;
+    let mut n = 0;
+    for a in self.actors.iter_mut() {
+      a.selected = a.scrx + t > minx && a.scrx < maxx && a.scry + t > miny && a.scry < maxy;
+      if a.selected { n = n + 1; }
+    }
+    n
+  }
+
+  pub fn add_building<T: BuildableEntity + 'static> (&mut self, mut bldg: T) {
+    bldg.setentityid(self.entityindex);
+    let mut b = Box::new(bldg);
+    b.setentityid(self.entityindex);
+    self.buildings.insert(self.entityindex, b);
+    self.entityindex = self.entityindex + 1;
+  }
+
+  /// Add a building that already has an entity id, e.g. one read back from a save
+  pub fn insert_building<T: BuildableEntity + 'static> (&mut self, bldg: T) {
+    let id = bldg.getentityid();
+    self.buildings.insert(id, Box::new(bldg));
+    if id >= self.entityindex { self.entityindex = id + 1; }
+  }
+
+  pub fn get_building_mut(&mut self, id: u64) -> Option<&mut Box<dyn BuildableEntity>> {
+    self.buildings.get_mut(&id)
+  }
+
+  pub fn remove_actor(&mut self, id: u64) {
+    self.actors.retain(|a| a.entityid != id);
+  }
+
+  pub fn remove_building (&mut self, id: u64) {
+    self.buildings.remove(&id);
+  }
+
+  /// Hand finished path searches to the actors that asked for them, see PathService::poll and
+  /// PathService::wait. A path found on an older map is kept if every tile on it is still
+  /// walkable, otherwise it is searched for again
+  pub fn collect_paths(&mut self, results: Vec<PathResult>, paths: &mut PathService, map: &Map) {
+    for r in results {
+      let a = match self.actors.iter_mut().find(|a| a.entityid == r.actor) {
+        Some(a) => a,
+        None => continue,
+      };
+      if a.pending.map(|(t, _, _)| t) != Some(r.ticket) { continue; } // Superseded by a newer order
+      let current = r.version == map.version;
+      match r.path {
+        Some(path) if current || path.iter().all(|p| map.ispassable(p.0, p.1)) => {
+          a.pending = None;
+          a.setpath(path);
+        }
+        None if current => { a.pending = None; }
+        _ if r.adjacent => {
+          if map.is_reachable_adjacent(&r.from, &r.to) {
+            a.pending = Some((paths.request_adjacent(r.actor, r.from, r.to.clone(), map), (r.to.0, r.to.1), true));
+          } else {
+            a.pending = None;
+          }
+        }
+        _ => {
+          if map.is_reachable(&r.from, &r.to) {
+            a.pending = Some((paths.request(r.actor, r.from, r.to.clone(), map), (r.to.0, r.to.1), false));
+          } else {
+            a.pending = None;
+          }
+        }
+      }
+    }
+  }
+
+  /// React to tiles whose path cost changed: actors now stuck in something solid are moved to the
+  /// nearest free tile, and actors whose remaining path crosses a changed tile plan a new one
+  pub fn handle_map_changes(&mut self, changed: &[(i32, i32)], map: &Map, paths: &mut PathService) {
+    for a in self.actors.iter_mut() {
+      let (x, y) = a.getoccupiedtile();
+      if !map.ispassable(x, y) {
+        let dest = if a.moving { a.steps.last().cloned() } else { None };
+        if let Some((nx, ny)) = map.nearest_passable(x, y) {
+          a.place_at(nx, ny);
+          if let Some((dx, dy)) = dest { a.setmovetarget(dx, dy, map, paths); }
+        }
+      } else if a.moving && a.steps.iter().any(|s| changed.contains(s)) {
+        a.replan(map, paths);
+      }
+    }
+  }
+
+  /// Actor that has claimed tile (x, y) as where it ends up, if any
+  pub fn reserved_by(&self, x: i32, y: i32) -> Option<u64> {
+    self.reservations.get(&(x, y)).cloned()
+  }
+
+  /// Give every actor its own destination tile. Standing actors keep the tile they are on,
+  /// moving ones that would end on a taken tile get sent on to the nearest free one
+  fn reserve_destinations(&mut self, map: &Map) {
+    self.reservations.clear();
+    for a in self.actors.iter().filter(|a| !a.moving) {
+      self.reservations.insert(a.gettile(), a.entityid);
+    }
+    for a in self.actors.iter_mut().filter(|a| a.moving) {
+      let mut dest = match a.steps.last() { Some(d) => *d, None => continue };
+      if self.reservations.get(&dest).is_some_and(|o| *o != a.entityid) {
+        // Stop short of the taken tile instead of walking through it
+        let prev = if a.steps.len() > 1 { a.steps[a.steps.len() - 2] } else { a.gettile() };
+        let taken = &self.reservations;
+        let aside = map.path_aside(&Pos(prev.0, prev.1), |p| (p.0, p.1) == dest,
+          |p| (p.0, p.1) == dest || taken.contains_key(&(p.0, p.1)), ASIDE_STEPS);
+        if let Some(aside) = aside {
+          a.steps.pop();
+          a.steps.extend(aside.iter().skip(1).map(|p| (p.0, p.1)));
+          dest = *a.steps.last().unwrap();
+        }
+      }
+      self.reservations.insert(dest, a.entityid);
+    }
+  }
+
+  /// Send actor i off the other's path and back again, so the other can get past
+  fn step_aside(&mut self, i: usize, other: usize, claimed: &HashMap<(i32, i32), u64>, map: &Map) -> bool {
+    let here = self.actors[i].gettile();
+    let me = self.actors[i].entityid;
+    let (theirs, there) = (&self.actors[other].steps, self.actors[other].gettile());
+    let aside = map.path_aside(&Pos(here.0, here.1),
+      |p| claimed.get(&(p.0, p.1)).is_some_and(|o| *o != me),
+      |p| theirs.contains(&(p.0, p.1)) || (p.0, p.1) == there,
+      ASIDE_STEPS);
+    let aside = match aside { Some(a) => a, None => { return false; } };
+    let a = &mut self.actors[i];
+    let mut steps: Vec<(i32, i32)> = aside.iter().skip(1).map(|p| (p.0, p.1)).collect();
+    if a.moving {
+      // Come back the same way and carry on
+      steps.extend(aside.iter().rev().skip(1).map(|p| (p.0, p.1)));
+      steps.extend(a.steps.iter().cloned());
+    }
+    a.steps = steps;
+    a.moving = true;
+    a.waited = 0;
+    true
+  }
+
+  /// One simulation tick. deltaT should always be the same, see PlayState's TICK_MS
+  pub fn update(&mut self, deltaT: u32, _tsize: f32, map: &Map) {
+    for a in self.actors.iter_mut() {
+      a.prevx = a.scrx;
+      a.prevy = a.scry;
+    }
+    self.reserve_destinations(map);
+
+    // Each actor holds the tile it is on and, once it starts moving, the tile it is moving into.
+    // Actors go one at a time, so whoever asks for a tile first gets it and the other waits
+    let mut claimed: HashMap<(i32, i32), u64> = self.actors.iter().map(|a| (a.gettile(), a.entityid)).collect();
+    for i in 0..self.actors.len() {
+      let me = self.actors[i].entityid;
+      if let Some(next) = self.actors[i].nextstep() {
+        if let Some(&other) = claimed.get(&next).filter(|o| **o != me) {
+          self.actors[i].waited = self.actors[i].waited + deltaT;
+          let j = self.actors.iter().position(|a| a.entityid == other).unwrap();
+          let standing = !self.actors[j].moving && !self.actors[j].ispending();
+          let headon = self.actors[j].nextstep() == Some(self.actors[i].gettile());
+          if standing {
+            self.step_aside(j, i, &claimed, map); // Idle actors make way
+          } else if headon || self.actors[i].waited > WAIT_LIMIT {
+            // Whoever came later gives way; if it can't, the other one tries
+            let (yielder, keeper) = if me > other { (i, j) } else { (j, i) };
+            if !self.step_aside(yielder, keeper, &claimed, map) {
+              self.step_aside(keeper, yielder, &claimed, map);
+            }
+          }
+          continue;
+        }
+        claimed.insert(next, me);
+      }
+      self.actors[i].waited = 0;
+      self.actors[i].update(deltaT);
+    }
+    // Doors swing open while someone stands in or is stepping into them
+    let busy: Vec<(i32, i32)> = self.actors.iter()
+      .flat_map(|a| Some(a.getoccupiedtile()).into_iter().chain(a.nextstep()))
+      .collect();
+    for (_, b) in self.buildings.iter_mut() {
+      let open = busy.contains(&b.getoccupiedtile());
+      b.setopen(open);
+    }
+  }
+
+  pub fn normalize(x: i32, y: i32) -> (f32, f32) {
+    let (x, y) = (x as f32, y as f32);
+    let s = ((x * x) + (y * y)).sqrt();
+
+    (x / s, y / s)
+  }
+
+  pub fn normalize_withspeed(a: &mut Actor, x: f32, y: f32) -> (f32, f32) {
+    let s = ((x * x) + (y * y)).sqrt();
+    if s == 0.0 { return (0.0, 0.0); }
+
+    ((x / s) * a.getspeed(), (y / s) * a.getspeed())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+#[test]
+  fn test_normalize() {
+    let a = Entities::normalize(5555, 0);
+    assert_eq!(a, (1.0, 0.0));
+  }
+
+#[test]
+  fn test_selection() {
+    let mut e = Entities::new();
+    e.add_actor(Actor::new(0, 1, 1, 1.0));
+    e.add_actor(Actor::new(0, 5, 1, 1.0));
+    e.add_actor(Actor::new(0, 5, 9, 1.0));
+    let t = TILESIZE as f32;
+
+    assert!(e.select_at(5.5 * t, 1.5 * t));
+    assert_eq!(e.get_selected().map(|a| a.getentityid()).collect::<Vec<_>>(), vec![1]);
+
+    assert_eq!(e.select_in(0.0, 0.0, 6.0 * t, 2.0 * t), 2);
+    assert_eq!(e.get_selected().map(|a| a.getentityid()).collect::<Vec<_>>(), vec![0, 1]);
+
+    assert!(!e.select_at(3.5 * t, 3.5 * t));
+    assert_eq!(e.get_selected().count(), 0);
+  }
+
+#[test]
+  fn test_blocked_paths() {
+    let mut map = super::super::map::test_map(20, 10);
+    let mut paths = PathService::new(1);
+    let mut e = Entities::new();
+    e.add_actor(Actor::new(0, 1, 5, 1.0));
+    e.add_actor(Actor::new(0, 8, 8, 1.0));
+    e.get_actor_by_id(0).unwrap().setpath(map.getpath(Pos(1, 5), Pos(15, 5)).unwrap());
+    assert!(e.getactors()[0].getsteps().contains(&(8, 5)));
+
+    let mut walls = Entities::new();
+    map.set_building_at(8, 5, Wall::new(0, 8, 5, 1.0, 99), &mut walls).unwrap();
+    map.set_building_at(8, 8, Wall::new(0, 8, 8, 1.0, 99), &mut walls).unwrap();
+    let changed = map.take_changes();
+    assert_eq!(changed, vec![(8, 5), (8, 8)]);
+    e.handle_map_changes(&changed, &map, &mut paths);
+
+    assert!(e.getactors()[0].ispending());
+    let (x, y) = e.getactors()[1].getoccupiedtile();
+    assert!(map.ispassable(x, y));
+    assert!((x - 8).abs() <= 1 && (y - 8).abs() <= 1);
+  }
+
+#[test]
+  fn test_corridor_and_reservations() {
+    // One-tile corridor along y = 1 with a single alcove at (6, 0)
+    let mut map = super::super::map::test_map(12, 3);
+    let mut walls = Entities::new();
+    for x in 0..12 {
+      for y in [0, 2].iter().cloned() {
+        if (x, y) == (6, 0) { continue; }
+        map.set_building_at(x, y, Wall::new(0, x, y, 1.0, 99), &mut walls).unwrap();
+      }
+    }
+    let mut e = Entities::new();
+    e.add_actor(Actor::new(0, 1, 1, 1.0));
+    e.add_actor(Actor::new(0, 10, 1, 1.0));
+    let there = map.getpath(Pos(1, 1), Pos(10, 1)).unwrap();
+    let back = map.getpath(Pos(10, 1), Pos(1, 1)).unwrap();
+    e.get_actor_by_id(0).unwrap().setpath(there);
+    e.get_actor_by_id(1).unwrap().setpath(back);
+    for _ in 0..2000 {
+      e.update(16, 64.0, &map);
+      let (a, b) = (e.getactors()[0].gettile(), e.getactors()[1].gettile());
+      assert_ne!(a, b);
+    }
+    assert_eq!(e.getactors()[0].getoccupiedtile(), (10, 1));
+    assert_eq!(e.getactors()[1].getoccupiedtile(), (1, 1));
+
+    // Two actors sent to the same tile end up side by side
+    let map = super::super::map::test_map(10, 10);
+    let mut e = Entities::new();
+    e.add_actor(Actor::new(0, 1, 1, 1.0));
+    e.add_actor(Actor::new(0, 1, 8, 1.0));
+    e.get_actor_by_id(0).unwrap().setpath(map.getpath(Pos(1, 1), Pos(5, 5)).unwrap());
+    e.get_actor_by_id(1).unwrap().setpath(map.getpath(Pos(1, 8), Pos(5, 5)).unwrap());
+    for _ in 0..1000 {
+      e.update(16, 64.0, &map);
+    }
+    let (a, b) = (e.getactors()[0].getoccupiedtile(), e.getactors()[1].getoccupiedtile());
+    assert!(a == (5, 5) || b == (5, 5));
+    assert_ne!(a, b);
+    assert_eq!(e.reserved_by(5, 5), Some(if a == (5, 5) { 0 } else { 1 }));
+  }
+}
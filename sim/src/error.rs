@@ -0,0 +1,33 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum SimError {
+  OutOfBounds,
+  Occupied,
+  NoPath,
+  BadSave(String),
+  BadDefinition(String),
+  Io(io::Error),
+}
+
+pub type SimResult<T> = Result<T, SimError>;
+
+impl fmt::Display for SimError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SimError::OutOfBounds => write!(f, "Tile out of bounds"),
+      SimError::Occupied => write!(f, "Tile already has a building"),
+      SimError::NoPath => write!(f, "No path found"),
+      SimError::BadSave(msg) => write!(f, "Bad save file: {}", msg),
+      SimError::BadDefinition(msg) => write!(f, "Bad definition: {}", msg),
+      SimError::Io(e) => write!(f, "IO error: {}", e),
+    }
+  }
+}
+
+impl std::error::Error for SimError {}
+
+impl From<io::Error> for SimError {
+  fn from(e: io::Error) -> SimError { SimError::Io(e) }
+}
@@ -0,0 +1,155 @@
+use crate::map::Map;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ItemKind {
+  Wood,
+  Stone,
+  Food,
+}
+
+pub const ITEMS: [ItemKind; 3] = [ItemKind::Wood, ItemKind::Stone, ItemKind::Food];
+
+impl ItemKind {
+  pub fn name(&self) -> &'static str {
+    match self {
+      ItemKind::Wood => "wood",
+      ItemKind::Stone => "stone",
+      ItemKind::Food => "food",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<ItemKind> {
+    ITEMS.iter().cloned().find(|k| k.name() == name)
+  }
+
+  /// Most of this kind that fits on one tile, and that one actor carries at a time
+  pub fn stacklimit(&self) -> u32 {
+    match self {
+      ItemKind::Wood => 50,
+      ItemKind::Stone => 25,
+      ItemKind::Food => 30,
+    }
+  }
+
+  /// Name of its sprite in import.xml
+  pub fn spritename(&self) -> &'static str {
+    match self {
+      ItemKind::Wood => "wood0",
+      ItemKind::Stone => "stone0",
+      ItemKind::Food => "food0",
+    }
+  }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stack {
+  pub kind: ItemKind,
+  pub count: u32,
+}
+
+impl Stack {
+  pub fn new(kind: ItemKind, count: u32) -> Self {
+    Stack { kind, count }
+  }
+}
+
+/// A stockpile: tiles colonists haul the kinds of item it accepts to
+pub struct Zone {
+  pub id: u64,
+  pub tiles: Vec<(i32, i32)>,
+  pub accepts: Vec<ItemKind>,
+}
+
+impl Zone {
+  pub fn accepts(&self, kind: ItemKind) -> bool { self.accepts.contains(&kind) }
+}
+
+pub struct Stockpiles {
+  zones: Vec<Zone>,
+  nextid: u64,
+}
+
+impl Default for Stockpiles {
+  fn default() -> Self { Stockpiles::new() }
+}
+
+impl Stockpiles {
+  pub fn new() -> Self {
+    Stockpiles { zones: Vec::new(), nextid: 0 }
+  }
+
+  pub fn getzones(&self) -> &Vec<Zone> { &self.zones }
+
+  /// Mark tiles as a new stockpile. Tiles that can't be walked on or are already in a stockpile
+  /// are left out, None if that leaves nothing
+  pub fn add(&mut self, tiles: &[(i32, i32)], accepts: Vec<ItemKind>, map: &Map) -> Option<u64> {
+    let mut free: Vec<(i32, i32)> = tiles.iter().cloned()
+      .filter(|t| map.ispassable(t.0, t.1) && self.zone_at(t.0, t.1).is_none())
+      .collect();
+    free.sort();
+    free.dedup();
+    if free.is_empty() { return None; }
+    let id = self.nextid;
+    self.nextid = self.nextid + 1;
+    self.zones.push(Zone { id, tiles: free, accepts });
+    Some(id)
+  }
+
+  /// Put back a stockpile read from a save
+  pub fn restore(&mut self, id: u64, tiles: Vec<(i32, i32)>, accepts: Vec<ItemKind>) {
+    self.nextid = self.nextid.max(id + 1);
+    self.zones.push(Zone { id, tiles, accepts });
+  }
+
+  pub fn zone_at(&self, x: i32, y: i32) -> Option<&Zone> {
+    self.zones.iter().find(|z| z.tiles.contains(&(x, y)))
+  }
+
+  pub fn set_accepts(&mut self, id: u64, accepts: Vec<ItemKind>) {
+    if let Some(z) = self.zones.iter_mut().find(|z| z.id == id) { z.accepts = accepts; }
+  }
+
+  pub fn remove(&mut self, id: u64) -> bool {
+    let before = self.zones.len();
+    self.zones.retain(|z| z.id != id);
+    self.zones.len() != before
+  }
+
+  /// True if items of `kind` on this tile are already where they belong
+  pub fn stored(&self, x: i32, y: i32, kind: ItemKind) -> bool {
+    self.zone_at(x, y).is_some_and(|z| z.accepts(kind))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::map::test_map;
+
+  #[test]
+  fn test_stacks_and_zones() {
+    let mut map = test_map(10, 10);
+    assert_eq!(map.add_items_at(2, 2, ItemKind::Wood, 30), 0);
+    assert_eq!(map.add_items_at(2, 2, ItemKind::Wood, 30), 10); // Full at 50
+    assert_eq!(map.add_items_at(2, 2, ItemKind::Stone, 5), 5);  // One kind per tile
+    assert_eq!(map.take_items_at(2, 2, ItemKind::Wood, 60), 50);
+    assert!(map.get_items_at(2, 2).is_none());
+
+    // Spills over onto the nearest tiles with room
+    map.drop_items(5, 5, ItemKind::Stone, 60);
+    assert_eq!(map.get_items_at(5, 5).map(|s| s.count), Some(25));
+    let total: u32 = map.getitems().values().map(|s| s.count).sum();
+    assert_eq!((map.getitems().len(), total), (3, 60));
+
+    let mut piles = Stockpiles::new();
+    let id = piles.add(&[(1, 1), (1, 2), (1, 1)], vec![ItemKind::Wood], &map).unwrap();
+    assert_eq!(piles.getzones()[0].tiles.len(), 2);
+    assert!(piles.add(&[(1, 1)], vec![ItemKind::Food], &map).is_none()); // Already taken
+    assert!(piles.stored(1, 2, ItemKind::Wood));
+    assert!(!piles.stored(1, 2, ItemKind::Food));
+    piles.set_accepts(id, vec![ItemKind::Food]);
+    assert!(piles.stored(1, 2, ItemKind::Food));
+    assert!(piles.remove(id));
+    assert!(piles.zone_at(1, 1).is_none());
+  }
+}
@@ -0,0 +1,673 @@
+use crate::entities::{Entities, Entity, Actor, Wall, Door, Furniture, Blueprint, WALL_SPRITE, STONE_WALL_SPRITE, DOOR_SPRITE, BED_SPRITE, TABLE_SPRITE};
+use crate::entities::{WALL_MATERIALS, DOOR_MATERIALS, FURNITURE_MATERIALS};
+use crate::map::{Map, Pos};
+use crate::map::pathservice::PathService;
+use crate::needs::Need;
+use crate::items::{ItemKind, Stack, Stockpiles};
+use crate::error::SimResult;
+
+const BUILD_WORK: f32 = 3000.0;    // ms of work to put up a wall
+const STONE_WORK: f32 = 4500.0;    // ms of work to put up a stone wall
+const DOOR_WORK: f32 = 2000.0;     // ms of work to hang a door
+const FURNITURE_WORK: f32 = 2500.0; // ms of work to knock together a bed or table
+const DEMOLISH_WORK: f32 = 1500.0; // ms of work to knock down DEMOLISH_HP of building
+const DEMOLISH_HP: f32 = 100.0;
+const REFUND: f32 = 0.5;           // Share of its materials a demolished building leaves behind
+const CUT_WORK: f32 = 2000.0;      // ms of work to cut down a plant
+const UNLOAD_WORK: f32 = 500.0;    // ms to put down a load at a stockpile
+const HAUL_INTERVAL: u32 = 1000;   // ms between looking for loose items to haul
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum JobKind {
+  BuildWall,
+  BuildStoneWall,
+  BuildDoor,
+  BuildBed,
+  BuildTable,
+  Demolish,
+  Cut,
+  Haul,
+}
+
+impl JobKind {
+  fn worktime(&self) -> f32 {
+    match self {
+      JobKind::BuildWall => BUILD_WORK,
+      JobKind::BuildStoneWall => STONE_WORK,
+      JobKind::BuildDoor => DOOR_WORK,
+      JobKind::BuildBed | JobKind::BuildTable => FURNITURE_WORK,
+      JobKind::Demolish => DEMOLISH_WORK,
+      JobKind::Cut => CUT_WORK,
+      JobKind::Haul => UNLOAD_WORK,
+    }
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      JobKind::BuildWall => "build",
+      JobKind::BuildStoneWall => "buildstone",
+      JobKind::BuildDoor => "builddoor",
+      JobKind::BuildBed => "buildbed",
+      JobKind::BuildTable => "buildtable",
+      JobKind::Demolish => "demolish",
+      JobKind::Cut => "cut",
+      JobKind::Haul => "haul",
+    }
+  }
+
+  /// Building sprite a construction job ends up as, None for other jobs
+  pub fn sprite(&self) -> Option<u32> {
+    match self {
+      JobKind::BuildWall => Some(WALL_SPRITE),
+      JobKind::BuildStoneWall => Some(STONE_WALL_SPRITE),
+      JobKind::BuildDoor => Some(DOOR_SPRITE),
+      JobKind::BuildBed => Some(BED_SPRITE),
+      JobKind::BuildTable => Some(TABLE_SPRITE),
+      JobKind::Demolish | JobKind::Cut | JobKind::Haul => None,
+    }
+  }
+
+  /// Materials that have to be brought to the site before building can start
+  pub fn cost(&self) -> Option<Stack> {
+    match self {
+      JobKind::BuildWall => Some(Stack::new(ItemKind::Wood, WALL_MATERIALS)),
+      JobKind::BuildStoneWall => Some(Stack::new(ItemKind::Stone, WALL_MATERIALS)),
+      JobKind::BuildDoor => Some(Stack::new(ItemKind::Wood, DOOR_MATERIALS)),
+      JobKind::BuildBed | JobKind::BuildTable => Some(Stack::new(ItemKind::Wood, FURNITURE_MATERIALS)),
+      JobKind::Demolish | JobKind::Cut | JobKind::Haul => None,
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<JobKind> {
+    match name {
+      "build" => Some(JobKind::BuildWall),
+      "buildstone" => Some(JobKind::BuildStoneWall),
+      "builddoor" => Some(JobKind::BuildDoor),
+      "buildbed" => Some(JobKind::BuildBed),
+      "buildtable" => Some(JobKind::BuildTable),
+      "demolish" => Some(JobKind::Demolish),
+      "cut" => Some(JobKind::Cut),
+      "haul" => Some(JobKind::Haul),
+      _ => None,
+    }
+  }
+}
+
+pub struct Job {
+  pub id: u64,
+  pub kind: JobKind,
+  pub target: (i32, i32),
+  pub worker: Option<u64>, // Entity id of the actor doing it
+  pub progress: f32,       // ms of work done so far
+  pub haul: Option<Haul>,  // For hauls target is where the items are picked up
+}
+
+/// Moving `count` of `kind` to a stockpile tile or a construction site. While it's queued the
+/// items at the source and the room at `to` are reserved, so no two hauls count on the same ones
+pub struct Haul {
+  pub kind: ItemKind,
+  pub count: u32,
+  pub to: (i32, i32),
+  pub carried: bool, // Picked up and on the way
+}
+
+impl Job {
+  pub fn isdone(&self) -> bool { self.progress >= self.kind.worktime() }
+
+  /// False while a construction job is still waiting on its materials
+  pub fn isready(&self, map: &Map) -> bool {
+    match self.kind.cost() {
+      Some(c) => map.get_items_at(self.target.0, self.target.1).is_some_and(|s| s.kind == c.kind && s.count >= c.count),
+      None => true,
+    }
+  }
+}
+
+/// Work queue. Player actions add jobs, construction sites and loose items get hauls, and actors
+/// with nothing better to do claim the nearest one they can reach
+pub struct Jobs {
+  jobs: Vec<Job>,
+  nextid: u64,
+  haultimer: u32,
+}
+
+impl Default for Jobs {
+  fn default() -> Self { Jobs::new() }
+}
+
+impl Jobs {
+  pub fn new() -> Self {
+    Jobs { jobs: Vec::new(), nextid: 0, haultimer: 0 }
+  }
+
+  pub fn getjobs(&self) -> &Vec<Job> { &self.jobs }
+
+  /// Queue a job on a tile. Only one job per tile, returns None if there already is one
+  pub fn add(&mut self, kind: JobKind, x: i32, y: i32) -> Option<u64> {
+    if self.get_at(x, y).is_some() { return None; }
+    let id = self.nextid;
+    self.nextid = self.nextid + 1;
+    self.jobs.push(Job { id, kind, target: (x, y), worker: None, progress: 0.0, haul: None });
+    Some(id)
+  }
+
+  /// Re-queue a job read back from a save
+  pub fn restore(&mut self, kind: JobKind, x: i32, y: i32, progress: f32) {
+    if self.add(kind, x, y).is_some() {
+      self.jobs.last_mut().unwrap().progress = progress;
+    }
+  }
+
+  /// Queue a haul read back from a save. Nobody has it yet, whoever was carrying it put the load
+  /// down at `from`
+  pub fn restore_haul(&mut self, from: (i32, i32), kind: ItemKind, count: u32, to: (i32, i32)) {
+    self.push_haul(from, Haul { kind, count, to, carried: false });
+  }
+
+  /// Work planned on a tile. Hauls aren't counted, they don't stop anything being built there
+  pub fn get_at(&self, x: i32, y: i32) -> Option<&Job> {
+    self.jobs.iter().find(|j| j.target == (x, y) && j.haul.is_none())
+  }
+
+  /// True if a blueprint for `kind` could go on this tile. Items other than what it's built from
+  /// would be in the way of the materials, so they have to be moved first, and plants have to be
+  /// cut down first so their wood isn't lost
+  pub fn canplace(&self, kind: JobKind, x: i32, y: i32, map: &mut Map) -> bool {
+    let clear = match (map.get_items_at(x, y), kind.cost()) {
+      (Some(s), Some(c)) => s.kind == c.kind,
+      (Some(_), None) => false,
+      (None, _) => true,
+    };
+    clear && map.get_plant_at(x, y).is_none() && map.ispassable(x, y) && !map.get_building_at(x, y) && self.get_at(x, y).is_none()
+  }
+
+  /// Put down a blueprint and queue the job to build it. `kind` must be a construction job
+  pub fn place_blueprint(&mut self, kind: JobKind, x: i32, y: i32, entities: &mut Entities, map: &mut Map) -> bool {
+    let sprite = match kind.sprite() { Some(s) => s, None => { return false; } };
+    if !self.canplace(kind, x, y, map) { return false; }
+    let b = Blueprint::new(sprite, x, y, 1.0, entities.getindex());
+    if map.set_building_at(x, y, b, entities).is_err() { return false; }
+    self.add(kind, x, y).is_some()
+  }
+
+  /// Cancel whatever was planned on a tile: the job, and the blueprint if there is one
+  pub fn cancel_plan_at(&mut self, x: i32, y: i32, entities: &mut Entities, map: &mut Map) -> bool {
+    let cancelled = self.cancel_at(x, y, entities);
+    if map.get_building(x, y).is_some_and(|b| b.isblueprint()) {
+      return map.clear_building_at(x, y, entities).is_ok();
+    }
+    cancelled
+  }
+
+  /// Remove the job on a tile, stopping whoever was working on it
+  pub fn cancel_at(&mut self, x: i32, y: i32, entities: &mut Entities) -> bool {
+    match self.jobs.iter().position(|j| j.target == (x, y) && j.haul.is_none()) {
+      Some(i) => {
+        let j = self.jobs.remove(i);
+        if let Some(a) = j.worker.and_then(|w| entities.get_actor_by_id(w)) { a.stop(); }
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Job the actor is working on, if any
+  pub fn job_for(&self, actor: u64) -> Option<&Job> {
+    self.jobs.iter().find(|j| j.worker == Some(actor))
+  }
+
+  /// Put an actor's job back in the queue, e.g. when the player gives it other orders. A haul
+  /// it's carrying is dropped instead, and the load put down wherever it stands
+  pub fn release_actor(&mut self, actor: u64) {
+    self.jobs.retain(|j| !(j.worker == Some(actor) && j.haul.as_ref().is_some_and(|h| h.carried)));
+    for j in self.jobs.iter_mut().filter(|j| j.worker == Some(actor)) {
+      j.worker = None;
+    }
+  }
+
+  /// Move work along and finish what's done. Fails if a finished job couldn't change the map
+  pub fn update(&mut self, deltaT: u32, entities: &mut Entities, map: &mut Map, paths: &mut PathService) -> SimResult<()> {
+    // Work on, or give up, claimed jobs
+    let mut dropped = Vec::new(); // Hauls that fell through after the items were picked up
+    for j in self.jobs.iter_mut() {
+      let w = match j.worker { Some(w) => w, None => continue };
+      let carried = j.haul.as_ref().is_some_and(|h| h.carried);
+      let a = match entities.get_actor_by_id(w) {
+        Some(a) => a,
+        None => {
+          j.worker = None;
+          if carried { dropped.push(j.id); }
+          continue;
+        }
+      };
+      let here = a.getoccupiedtile();
+      if a.ispending() {
+        continue; // Still waiting on the way there
+      } else if a.ismoving() {
+        // Back to the queue if the way there has been walled off
+        if let Some((x, y)) = a.nextstep() {
+          if !map.ispassable(x, y) {
+            a.stop();
+            j.worker = None;
+            if carried { dropped.push(j.id); }
+          }
+        }
+      } else if let Some(h) = j.haul.as_mut() {
+        if !h.carried && here == j.target {
+          let took = map.take_items_at(j.target.0, j.target.1, h.kind, h.count);
+          if took > 0 { a.carrying = Some(Stack::new(h.kind, took)); }
+          if took > 0 && (here == h.to || a.setmovetarget(h.to.0, h.to.1, map, paths)) {
+            h.count = took;
+            h.carried = true;
+          } else {
+            dropped.push(j.id); // Gone, or nowhere to take them
+          }
+        } else if h.carried && here == h.to {
+          j.progress = j.progress + (deltaT as f32 * a.needs.workrate());
+        } else {
+          j.worker = None;
+          if h.carried { dropped.push(j.id); }
+        }
+      } else if !j.isready(map) {
+        j.worker = None; // Materials went missing, wait for more
+      } else if Pos::from(here).distance(&Pos(j.target.0, j.target.1)) == 1 {
+        // Tougher buildings take longer to knock down
+        let rate = match (j.kind, map.get_building(j.target.0, j.target.1)) {
+          (JobKind::Demolish, Some(b)) => DEMOLISH_HP / (b.gethitpoints().max(1) as f32),
+          _ => 1.0,
+        };
+        j.progress = j.progress + (deltaT as f32 * a.needs.workrate() * rate);
+        if j.kind.sprite().is_some() {
+          let p = (j.progress / j.kind.worktime()).min(1.0);
+          let id = map.get_building(j.target.0, j.target.1).map(|b| b.getentityid());
+          if let Some(b) = id.and_then(|id| entities.get_building_mut(id)) { b.setprogress(p); }
+        }
+      } else {
+        j.worker = None; // Stopped somewhere else, so it was interrupted
+      }
+    }
+    self.jobs.retain(|j| !dropped.contains(&j.id));
+
+    // Finish completed jobs
+    let mut i = 0;
+    while i < self.jobs.len() {
+      if self.jobs[i].isdone() {
+        let j = self.jobs.remove(i);
+        Jobs::complete(&j, entities, map)?;
+      } else {
+        i = i + 1;
+      }
+    }
+
+    // Anyone left holding items without a haul to take them on puts them down
+    for a in entities.getactors_mut().iter_mut() {
+      if a.carrying.is_none() { continue; }
+      let id = a.getentityid();
+      if self.jobs.iter().any(|j| j.worker == Some(id) && j.haul.is_some()) { continue; }
+      let (x, y) = a.getoccupiedtile();
+      if let Some(s) = a.carrying.take() { map.drop_items(x, y, s.kind, s.count); }
+    }
+    Ok(())
+  }
+
+  /// Queue hauls bringing construction sites their materials, then hauls for items that aren't in
+  /// a stockpile that takes them to the nearest stockpile tiles with room. Also drops hauls to
+  /// sites and stockpiles that have since gone or stopped taking them
+  pub fn plan_hauls(&mut self, deltaT: u32, stockpiles: &Stockpiles, map: &Map) {
+    self.haultimer = self.haultimer + deltaT;
+    if self.haultimer < HAUL_INTERVAL { return; }
+    self.haultimer = 0;
+    let sites: Vec<((i32, i32), Stack)> = self.jobs.iter()
+      .filter_map(|j| j.kind.cost().map(|c| (j.target, c)))
+      .collect();
+    self.jobs.retain(|j| match &j.haul {
+      Some(h) => sites.iter().any(|(t, c)| *t == h.to && c.kind == h.kind)
+        || stockpiles.zone_at(h.to.0, h.to.1).is_some_and(|z| z.accepts(h.kind)),
+      None => true,
+    });
+
+    // Materials come from wherever there are some, stockpiles included, but not other sites
+    for (site, cost) in sites.iter() {
+      let here = Pos(site.0, site.1);
+      let mut need = cost.count.saturating_sub(self.delivered(*site, cost.kind, map));
+      while need > 0 {
+        let source = map.getitems().iter()
+          .filter(|(t, s)| s.kind == cost.kind && !sites.iter().any(|(st, _)| st == *t))
+          .map(|(t, s)| (*t, s.count.saturating_sub(self.reserved(*t))))
+          .filter(|(t, left)| *left > 0 && map.is_reachable(&Pos(t.0, t.1), &here))
+          .min_by_key(|(t, _)| (here.distance(&Pos(t.0, t.1)), *t));
+        let (from, left) = match source { Some(s) => s, None => break };
+        let count = need.min(left).min(self.room_left(*site, cost.kind, map));
+        if count == 0 { break; } // Something else is lying there
+        self.push_haul(from, Haul { kind: cost.kind, count, to: *site, carried: false });
+        need = need - count;
+      }
+    }
+    if stockpiles.getzones().is_empty() { return; }
+
+    let mut loose: Vec<((i32, i32), Stack)> = map.getitems().iter()
+      .filter(|(t, s)| !stockpiles.stored(t.0, t.1, s.kind))
+      .filter(|(t, s)| !sites.iter().any(|(st, c)| st == *t && c.kind == s.kind))
+      .map(|(t, s)| (*t, *s))
+      .collect();
+    loose.sort_by_key(|(t, _)| *t);
+    for (from, s) in loose {
+      let mut left = s.count.saturating_sub(self.reserved(from));
+      while left > 0 {
+        let here = Pos(from.0, from.1);
+        let dest = stockpiles.getzones().iter()
+          .filter(|z| z.accepts(s.kind))
+          .flat_map(|z| z.tiles.iter())
+          .map(|t| (*t, self.room_left(*t, s.kind, map)))
+          .filter(|(t, room)| *room > 0 && map.is_reachable(&here, &Pos(t.0, t.1)))
+          .min_by_key(|(t, _)| (here.distance(&Pos(t.0, t.1)), *t));
+        let (to, room) = match dest { Some(d) => d, None => break };
+        let count = left.min(room).min(s.kind.stacklimit());
+        self.push_haul(from, Haul { kind: s.kind, count, to, carried: false });
+        left = left - count;
+      }
+    }
+  }
+
+  fn push_haul(&mut self, from: (i32, i32), haul: Haul) {
+    let id = self.nextid;
+    self.nextid = self.nextid + 1;
+    self.jobs.push(Job { id, kind: JobKind::Haul, target: from, worker: None, progress: 0.0, haul: Some(haul) });
+  }
+
+  /// Items on a tile that hauls still to pick them up are counting on
+  fn reserved(&self, t: (i32, i32)) -> u32 {
+    self.jobs.iter()
+      .filter(|j| j.target == t)
+      .filter_map(|j| j.haul.as_ref().filter(|h| !h.carried).map(|h| h.count))
+      .sum()
+  }
+
+  /// How many of `kind` a construction site has, counting what's on the way
+  fn delivered(&self, t: (i32, i32), kind: ItemKind, map: &Map) -> u32 {
+    let here = map.get_items_at(t.0, t.1).filter(|s| s.kind == kind).map_or(0, |s| s.count);
+    let coming: u32 = self.jobs.iter()
+      .filter_map(|j| j.haul.as_ref())
+      .filter(|h| h.to == t && h.kind == kind)
+      .map(|h| h.count)
+      .sum();
+    here + coming
+  }
+
+  /// Room for `kind` on a tile, less what queued hauls are already bringing
+  fn room_left(&self, t: (i32, i32), kind: ItemKind, map: &Map) -> u32 {
+    let mut room = map.room_at(t.0, t.1, kind);
+    for h in self.jobs.iter().filter_map(|j| j.haul.as_ref()).filter(|h| h.to == t) {
+      if h.kind != kind { return 0; }
+      room = room.saturating_sub(h.count);
+    }
+    room
+  }
+
+  /// True if there's a job nobody has that could be started now
+  pub fn anyopen(&self, map: &Map) -> bool {
+    self.jobs.iter().any(|j| j.worker.is_none() && j.isready(map))
+  }
+
+  /// Give the actor the nearest open job it can reach and send it there. False if there is none.
+  /// The path there is searched for in the background, the job waits for the actor meanwhile
+  pub fn claim(&mut self, a: &mut Actor, map: &Map, paths: &mut PathService) -> bool {
+    let from = Pos::from(a.getoccupiedtile());
+    let mut open: Vec<&mut Job> = self.jobs.iter_mut().filter(|j| j.worker.is_none() && j.isready(map)).collect();
+    open.sort_by_key(|j| from.distance(&Pos(j.target.0, j.target.1)));
+    for j in open {
+      let to = Pos(j.target.0, j.target.1);
+      // Hauls start on the items' tile, other work is done from next to it. Either way nothing
+      // is asked for if the actor is already there
+      if j.haul.is_some() {
+        if from != to && !map.is_reachable(&from, &to) { continue; }
+        a.stop();
+        a.setmovetarget(to.0, to.1, map, paths);
+      } else {
+        if from.distance(&to) != 1 && !map.is_reachable_adjacent(&from, &to) { continue; }
+        a.stop();
+        a.setworktarget(to.0, to.1, map, paths);
+      }
+      j.worker = Some(a.getentityid());
+      return true;
+    }
+    false
+  }
+
+  fn complete(j: &Job, entities: &mut Entities, map: &mut Map) -> SimResult<()> {
+    let (x, y) = j.target;
+    match j.kind {
+      JobKind::BuildWall | JobKind::BuildStoneWall | JobKind::BuildDoor | JobKind::BuildBed | JobKind::BuildTable => {
+        // Only a blueprint turns into a building; without one the plan was cancelled
+        if map.get_building(x, y).is_some_and(|b| b.isblueprint()) {
+          // The building replaces the blueprint before the materials are used, so if that fails
+          // both are still there
+          let e = entities.getindex();
+          match j.kind {
+            JobKind::BuildDoor => map.replace_building_at(x, y, Door::new(DOOR_SPRITE, x, y, 1.0, e), entities)?,
+            JobKind::BuildBed => map.replace_building_at(x, y, Furniture::new(BED_SPRITE, x, y, 1.0, e, Need::Rest), entities)?,
+            JobKind::BuildTable => map.replace_building_at(x, y, Furniture::new(TABLE_SPRITE, x, y, 1.0, e, Need::Joy), entities)?,
+            JobKind::BuildStoneWall => map.replace_building_at(x, y, Wall::new(STONE_WALL_SPRITE, x, y, 1.0, e), entities)?,
+            _ => map.replace_building_at(x, y, Wall::new(WALL_SPRITE, x, y, 1.0, e), entities)?,
+          }
+          if let Some(c) = j.kind.cost() { map.take_items_at(x, y, c.kind, c.count); }
+          // Anything else left on the site is pushed off it, walls don't leave room
+          let left = map.get_items_at(x, y).cloned();
+          if let Some(s) = left { map.take_items_at(x, y, s.kind, s.count); }
+          if let Some(s) = left { map.drop_items(x, y, s.kind, s.count); }
+        }
+      }
+      JobKind::Cut => {
+        if let Some(p) = map.take_plant_at(x, y) {
+          if p.wood() > 0 { map.drop_items(x, y, ItemKind::Wood, p.wood()); }
+        }
+      }
+      JobKind::Demolish => {
+        if map.get_building_at(x, y) {
+          let refund = map.get_building(x, y).and_then(|b| b.getmaterials());
+          map.clear_building_at(x, y, entities)?;
+          if let Some(s) = refund {
+            let count = (s.count as f32 * REFUND) as u32;
+            if count > 0 { map.drop_items(x, y, s.kind, count); }
+          }
+        }
+      }
+      JobKind::Haul => {
+        let to = j.haul.as_ref().map_or((x, y), |h| h.to);
+        if let Some(a) = j.worker.and_then(|w| entities.get_actor_by_id(w)) {
+          if let Some(s) = a.carrying.take() { map.drop_items(to.0, to.1, s.kind, s.count); }
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::entities::{Actor, Buildable};
+  use super::super::map::test_map;
+  use crate::plants::{Plant, PlantKind, STAGES};
+
+  /// Tick without a Brain: anyone standing around takes the nearest job
+  fn step(entities: &mut Entities, jobs: &mut Jobs, map: &mut Map, piles: &Stockpiles, paths: &mut PathService) {
+    entities.collect_paths(paths.wait(), paths, map);
+    entities.update(16, 64.0, map);
+    jobs.update(16, entities, map, paths).unwrap();
+    jobs.plan_hauls(16, piles, map);
+    for a in entities.getactors_mut().iter_mut() {
+      if !a.ismoving() && !a.ispending() && jobs.job_for(a.getentityid()).is_none() { jobs.claim(a, map, paths); }
+    }
+  }
+
+  #[test]
+  fn test_build_and_demolish() {
+    let mut map = test_map(20, 20);
+    let mut entities = Entities::new();
+    entities.add_actor(Actor::new(0, 2, 2, 1.0));
+    let mut jobs = Jobs::new();
+    let mut paths = PathService::new(1);
+    let piles = Stockpiles::new();
+
+    assert!(jobs.place_blueprint(JobKind::BuildWall, 10, 10, &mut entities, &mut map));
+    assert!(!jobs.place_blueprint(JobKind::BuildWall, 10, 10, &mut entities, &mut map));
+    assert!(map.ispassable(10, 10)); // Blueprints don't block
+    for _ in 0..200 {
+      step(&mut entities, &mut jobs, &mut map, &piles, &mut paths);
+    }
+    assert!(jobs.job_for(0).is_none()); // Nothing to build it with yet
+
+    map.add_items_at(3, 2, ItemKind::Wood, 15);
+    for _ in 0..700 {
+      step(&mut entities, &mut jobs, &mut map, &piles, &mut paths);
+    }
+    assert!(map.get_building_at(10, 10));
+    assert!(!map.get_building(10, 10).unwrap().isblueprint());
+    assert!(!map.ispassable(10, 10));
+    assert!(jobs.getjobs().is_empty());
+    assert_eq!(entities.getbuildings().len(), 1);
+    assert_eq!(map.get_items_at(3, 2).map(|s| s.count), Some(5));
+    assert!(map.get_items_at(10, 10).is_none());
+
+    // Half the wood back where it stood
+    jobs.add(JobKind::Demolish, 10, 10);
+    for _ in 0..300 {
+      step(&mut entities, &mut jobs, &mut map, &piles, &mut paths);
+    }
+    assert!(!map.get_building_at(10, 10));
+    assert!(map.ispassable(10, 10));
+    assert_eq!(map.get_items_at(10, 10).map(|s| (s.kind, s.count)), Some((ItemKind::Wood, WALL_MATERIALS / 2)));
+  }
+
+  #[test]
+  fn test_blueprint_on_items() {
+    let mut map = test_map(20, 20);
+    let mut entities = Entities::new();
+    entities.add_actor(Actor::new(0, 2, 2, 1.0));
+    let mut jobs = Jobs::new();
+    let mut paths = PathService::new(1);
+    let piles = Stockpiles::new();
+
+    // Only what it's built from may lie under a blueprint, and that counts towards it
+    map.add_items_at(6, 6, ItemKind::Stone, 5);
+    assert!(!jobs.canplace(JobKind::BuildWall, 6, 6, &mut map));
+    assert!(!jobs.place_blueprint(JobKind::BuildWall, 6, 6, &mut entities, &mut map));
+    assert!(jobs.place_blueprint(JobKind::BuildStoneWall, 6, 6, &mut entities, &mut map));
+    map.add_items_at(3, 2, ItemKind::Stone, 20);
+    for _ in 0..800 {
+      step(&mut entities, &mut jobs, &mut map, &piles, &mut paths);
+    }
+    assert!(!map.get_building(6, 6).unwrap().isblueprint());
+    assert!(map.get_items_at(6, 6).is_none());
+    assert_eq!(map.get_items_at(3, 2).map(|s| s.count), Some(15));
+
+    // Something dropped on a site later isn't hauled over, or the delivery would spill
+    assert!(jobs.place_blueprint(JobKind::BuildWall, 12, 12, &mut entities, &mut map));
+    map.add_items_at(12, 12, ItemKind::Stone, 1);
+    map.add_items_at(3, 3, ItemKind::Wood, 20);
+    for _ in 0..200 {
+      step(&mut entities, &mut jobs, &mut map, &piles, &mut paths);
+    }
+    assert!(jobs.getjobs().iter().all(|j| j.haul.is_none()));
+    assert_eq!(map.get_items_at(3, 3).map(|s| s.count), Some(20));
+  }
+
+  #[test]
+  fn test_cut_for_wood() {
+    let mut map = test_map(20, 20);
+    let mut entities = Entities::new();
+    entities.add_actor(Actor::new(0, 2, 2, 1.0));
+    let mut jobs = Jobs::new();
+    let mut paths = PathService::new(1);
+    let piles = Stockpiles::new();
+    let tree = Plant::new(PlantKind::Tree, STAGES - 1);
+    map.set_plant_at(8, 8, tree);
+    assert!(map.costmap[map.getmapvecidx(8, 8)] > 1);
+
+    jobs.add(JobKind::Cut, 8, 8);
+    for _ in 0..400 {
+      step(&mut entities, &mut jobs, &mut map, &piles, &mut paths);
+    }
+    assert!(jobs.getjobs().is_empty());
+    assert!(map.get_plant_at(8, 8).is_none());
+    assert_eq!(map.costmap[map.getmapvecidx(8, 8)], 1);
+    assert_eq!(map.get_items_at(8, 8).map(|s| (s.kind, s.count)), Some((ItemKind::Wood, tree.wood())));
+
+    // Nothing is built over a plant, it has to be cut down first
+    map.set_plant_at(4, 8, Plant::new(PlantKind::Shrub, 0));
+    assert!(!jobs.place_blueprint(JobKind::BuildWall, 4, 8, &mut entities, &mut map));
+  }
+
+  #[test]
+  fn test_stone_walls() {
+    let wood = Wall::new(WALL_SPRITE, 0, 0, 1.0, 0);
+    let stone = Wall::new(STONE_WALL_SPRITE, 0, 0, 1.0, 0);
+    assert_eq!(stone.getmaterials().map(|s| s.kind), Some(ItemKind::Stone));
+    assert!(stone.gethitpoints() > wood.gethitpoints());
+    assert_eq!(JobKind::BuildStoneWall.cost().map(|s| s.kind), Some(ItemKind::Stone));
+  }
+
+  #[test]
+  fn test_cancel_blueprint() {
+    let mut map = test_map(20, 20);
+    let mut entities = Entities::new();
+    let mut jobs = Jobs::new();
+    jobs.place_blueprint(JobKind::BuildWall, 4, 4, &mut entities, &mut map);
+    assert_eq!(entities.getbuildings().len(), 1);
+
+    assert!(jobs.cancel_plan_at(4, 4, &mut entities, &mut map));
+    assert!(entities.getbuildings().is_empty());
+    assert!(!map.get_building_at(4, 4));
+    assert!(jobs.getjobs().is_empty());
+  }
+
+  #[test]
+  fn test_hauls_reserve_items_and_room() {
+    let mut map = test_map(20, 20);
+    let mut entities = Entities::new();
+    let mut jobs = Jobs::new();
+    let mut paths = PathService::new(1);
+    let mut piles = Stockpiles::new();
+    map.add_items_at(2, 2, ItemKind::Wood, 40);
+    map.add_items_at(3, 2, ItemKind::Wood, 30);
+    map.add_items_at(12, 12, ItemKind::Wood, 20); // Half full already
+    piles.add(&[(12, 12)], vec![ItemKind::Wood], &map);
+
+    jobs.plan_hauls(HAUL_INTERVAL, &piles, &map);
+    jobs.plan_hauls(HAUL_INTERVAL, &piles, &map);
+    let planned: u32 = jobs.getjobs().iter().map(|j| j.haul.as_ref().unwrap().count).sum();
+    assert_eq!(planned, 30); // Only the room that's left, however often it's planned
+
+    entities.add_actor(Actor::new(0, 2, 5, 1.0));
+    for _ in 0..1500 {
+      step(&mut entities, &mut jobs, &mut map, &piles, &mut paths);
+    }
+    assert!(jobs.getjobs().is_empty());
+    assert_eq!(map.get_items_at(12, 12).map(|s| s.count), Some(50));
+    let loose: u32 = map.getitems().iter().filter(|(t, _)| **t != (12, 12)).map(|(_, s)| s.count).sum();
+    assert_eq!(loose, 40);
+  }
+
+  #[test]
+  fn test_release_on_interrupt() {
+    let map = test_map(20, 20);
+    let mut entities = Entities::new();
+    entities.add_actor(Actor::new(0, 2, 2, 1.0));
+    let mut jobs = Jobs::new();
+    jobs.add(JobKind::Demolish, 15, 15);
+    let mut paths = PathService::new(1);
+    assert!(jobs.claim(entities.get_actor_by_id(0).unwrap(), &map, &mut paths));
+    assert!(jobs.job_for(0).is_some());
+    // The way there is found in the background, and isn't saved as an order
+    let a = &entities.getactors()[0];
+    assert!(a.ispending() && a.getorder().is_none());
+    entities.collect_paths(paths.wait(), &mut paths, &map);
+    assert_eq!(entities.getactors()[0].getsteps().last().map(|t| Pos::from(*t).distance(&Pos(15, 15))), Some(1));
+
+    jobs.release_actor(0);
+    entities.get_actor_by_id(0).unwrap().stop();
+    assert!(jobs.job_for(0).is_none());
+    assert!(jobs.get_at(15, 15).unwrap().worker.is_none());
+  }
+}
@@ -0,0 +1,21 @@
+#![allow(non_snake_case)]
+#![allow(non_camel_case_types)]
+#![allow(clippy::assign_op_pattern)] // Counters here are written out as `x = x + 1`
+//! The world simulation: map, pathfinding, entities, plants, jobs and what actors decide to do.
+//! Nothing in here needs a window, so it can be run headless from tests and the command line
+
+pub mod error;
+pub mod rng;
+pub mod tiles;
+pub mod clock;
+pub mod map;
+pub mod entities;
+pub mod items;
+pub mod plants;
+pub mod needs;
+pub mod ai;
+pub mod jobs;
+pub mod save;
+pub mod world;
+
+pub const TILESIZE: i32 = 64; // Map pixels per tile side. Actor positions are kept in map pixels
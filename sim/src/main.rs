@@ -0,0 +1,92 @@
+//! Run the simulation without a window, e.g.
+//! `rustworld_sim --seed 1234 --width 200 --height 200 --ticks 10000 --save out.sav`.
+//! `--behaviours file` swaps in behaviours written like ai::STANDARD
+use rustworld_sim::ai::Brain;
+use rustworld_sim::clock::TICK_MS;
+use rustworld_sim::jobs::JobKind;
+use rustworld_sim::map::Pos;
+use rustworld_sim::entities::Entity;
+use rustworld_sim::save;
+use rustworld_sim::tiles::TileRegistry;
+use rustworld_sim::world::World;
+use std::fs::File;
+use std::io::BufWriter;
+use std::time::Instant;
+
+const WALLS: i32 = 10; // Blueprints queued in front of the colonists, so there is work to do
+
+fn main() {
+  let (mut seed, mut width, mut height, mut ticks, mut colonists) = (1234u32, 300, 300, 10000u32, 3);
+  let mut savepath: Option<String> = None;
+  let mut behaviours: Option<String> = None;
+  let mut args = std::env::args().skip(1);
+  while let Some(arg) = args.next() {
+    let value = args.next();
+    let num = value.as_ref().and_then(|v| v.trim().parse::<u32>().ok());
+    match (arg.as_ref(), num) {
+      ("--seed", Some(n)) => { seed = n; }
+      ("--width", Some(n)) => { width = n as i32; }
+      ("--height", Some(n)) => { height = n as i32; }
+      ("--ticks", Some(n)) => { ticks = n; }
+      ("--colonists", Some(n)) => { colonists = n as i32; }
+      ("--save", _) if value.is_some() => { savepath = value; }
+      ("--behaviours", _) if value.is_some() => { behaviours = value; }
+      _ => {
+        println!("Usage: rustworld_sim [--seed n] [--width n] [--height n] [--ticks n] [--colonists n] [--save path] [--behaviours path]");
+        return;
+      }
+    }
+  }
+
+  let tiles = TileRegistry::standard();
+  let start = Instant::now();
+  let mut world = World::new(tiles, seed, width, height, colonists, 0);
+  world.waitforpaths = true; // Nothing to draw, and the same seed should give the same run
+  println!("Generated {} x {} map from seed {} in {:?}", width, height, seed, start.elapsed());
+  if let Some(path) = behaviours {
+    match std::fs::read_to_string(&path).map_err(|e| e.into()).and_then(|t| Brain::parse(&t)) {
+      Ok(mut b) => {
+        b.setseed(seed);
+        world.brain = b;
+      }
+      Err(e) => { println!("Couldn't read {}: {}", path, e); return; }
+    }
+  }
+
+  if let Some(a) = world.entities.getactors().first() {
+    let home = Pos::from(a.getoccupiedtile());
+    for x in (home.0 - (WALLS / 2))..(home.0 + (WALLS / 2)) {
+      if world.map.is_reachable(&home, &Pos(x, home.1 + 3)) {
+        world.jobs.place_blueprint(JobKind::BuildWall, x, home.1 + 3, &mut world.entities, &mut world.map);
+      }
+    }
+  }
+  let queued = world.jobs.getjobs().len();
+
+  let start = Instant::now();
+  for _ in 0..ticks {
+    if let Err(e) = world.tick() {
+      println!("Stopped: {}", e);
+      break;
+    }
+  }
+  let built = world.entities.getbuildings().values().filter(|b| !b.isblueprint()).count();
+  println!("Ran {} ticks ({} s of game time) in {:?}", ticks, (ticks * TICK_MS) / 1000, start.elapsed());
+  println!("{} of {} queued jobs done, {} buildings standing", queued - world.jobs.getjobs().len(), queued, built);
+  let grown = world.map.getplants().values().filter(|p| p.isgrown()).count();
+  println!("{} plants, {} of them grown", world.map.getplants().len(), grown);
+  for a in world.entities.getactors().iter() {
+    let doing = a.getbehaviour().map_or("idle", |b| b.verb());
+    println!("  actor {} at {:?}, {}{}", a.getentityid(), a.getoccupiedtile(), doing, if a.ismoving() { ", moving" } else { "" });
+  }
+
+  if let Some(path) = savepath {
+    let result = File::create(&path).map_err(|e| e.into()).and_then(|f| {
+      save::save_game(&mut BufWriter::new(f), &world, (0.0, 0.0), 2)
+    });
+    match result {
+      Ok(_) => println!("Saved to {}", path),
+      Err(e) => println!("Save failed: {}", e),
+    }
+  }
+}
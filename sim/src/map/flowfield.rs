@@ -0,0 +1,132 @@
+use super::{Map, Pos, STEP_COST, DIAGONAL_COST, getmapvecidx};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+const MAX_FIELDS: usize = 8; // Goals kept in the cache at once
+
+/// Integration field for one goal: the cost of walking to the goal from every tile.
+/// Any number of actors can follow it downhill without searching for their own path
+pub struct FlowField {
+  goal: Pos,
+  version: u64, // Map version the field was built on
+  width: i32,
+  height: i32,
+  cost: Vec<usize>,
+}
+
+impl FlowField {
+  /// Dijkstra outwards from the goal over the costmap, with the same step rules as Pos::successors
+  pub fn new(goal: Pos, map: &Map) -> Self {
+    let (w, h) = (map.width, map.height);
+    let mut cost = vec![usize::MAX; (w * h) as usize];
+    let mut open = BinaryHeap::new();
+    if map.ispassable(goal.0, goal.1) {
+      cost[getmapvecidx(goal.0, goal.1, w)] = 0;
+      open.push(Reverse((0, goal.0, goal.1)));
+    }
+    while let Some(Reverse((c, x, y))) = open.pop() {
+      if c > cost[getmapvecidx(x, y, w)] { continue; }
+      // Stepping from a neighbour onto (x, y) costs what (x, y) costs, the same as going forwards
+      let tilecost = map.costmap[getmapvecidx(x, y, w)];
+      for (n, _) in Pos(x, y).successors(&map.costmap, w, h) {
+        let step = if n.0 != x && n.1 != y { DIAGONAL_COST } else { STEP_COST };
+        let nc = c + (tilecost * step);
+        let idx = getmapvecidx(n.0, n.1, w);
+        if nc < cost[idx] {
+          cost[idx] = nc;
+          open.push(Reverse((nc, n.0, n.1)));
+        }
+      }
+    }
+    FlowField { goal, version: map.version, width: w, height: h, cost }
+  }
+
+  pub fn getgoal(&self) -> &Pos { &self.goal }
+
+  /// Cost of walking from (x, y) to the goal, None if it can't be reached
+  pub fn cost_at(&self, x: i32, y: i32) -> Option<usize> {
+    if x < 0 || x >= self.width || y < 0 || y >= self.height { return None; }
+    let c = self.cost[getmapvecidx(x, y, self.width)];
+    if c < usize::MAX { Some(c) } else { None }
+  }
+
+  /// Tiles from `from` to the goal, following the field downhill
+  pub fn path_from(&self, from: &Pos, map: &Map) -> Option<Vec<Pos>> {
+    let mut here = self.cost_at(from.0, from.1)?;
+    let mut path = vec![from.clone()];
+    let mut p = from.clone();
+    while p != self.goal {
+      let next = p.successors(&map.costmap, self.width, self.height).into_iter()
+        .filter_map(|(n, _)| self.cost_at(n.0, n.1).map(|c| (c, n)))
+        .min()?;
+      if next.0 >= here { return None; } // Map changed since the field was built
+      here = next.0;
+      p = next.1;
+      path.push(p.clone());
+    }
+    Some(path)
+  }
+}
+
+/// Flow fields by goal tile. A field is rebuilt once the costmap has changed since it was made
+pub struct FlowFields {
+  fields: HashMap<Pos, FlowField>,
+  order: Vec<Pos>, // Oldest first, for dropping fields when the cache is full
+}
+
+impl Default for FlowFields {
+  fn default() -> Self { FlowFields::new() }
+}
+
+impl FlowFields {
+  pub fn new() -> Self {
+    FlowFields { fields: HashMap::new(), order: Vec::new() }
+  }
+
+  pub fn get(&mut self, goal: &Pos, map: &Map) -> &FlowField {
+    let fresh = self.fields.get(goal).is_some_and(|f| f.version == map.version);
+    if !fresh {
+      self.order.retain(|g| g != goal);
+      if self.order.len() >= MAX_FIELDS {
+        let old = self.order.remove(0);
+        self.fields.remove(&old);
+      }
+      self.fields.insert(goal.clone(), FlowField::new(goal.clone(), map));
+      self.order.push(goal.clone());
+    }
+    &self.fields[goal]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::test_map;
+  use super::super::super::entities::{Entities, Wall};
+
+  #[test]
+  fn test_flow_matches_astar() {
+    let mut map = test_map(30, 20);
+    let mut entities = Entities::new();
+    for y in 0..15 {
+      map.set_building_at(12, y, Wall::new(0, 12, y, 1.0, entities.getindex()), &mut entities).unwrap();
+    }
+    let mut flows = FlowFields::new();
+    let goal = Pos(25, 3);
+    for from in [Pos(1, 1), Pos(5, 18), Pos(11, 0)].iter() {
+      let field = flows.get(&goal, &map);
+      let path = field.path_from(from, &map).unwrap();
+      assert_eq!(path.last(), Some(&goal));
+      assert!(path.iter().all(|p| map.ispassable(p.0, p.1)));
+      // Same length of walk as A* over the same costs
+      let (_, best) = pathfinding::prelude::astar(from, |p| p.successors(&map.costmap, 30, 20), |p| p.octile(&goal), |p| *p == goal).unwrap();
+      assert_eq!(field.cost_at(from.0, from.1), Some(best));
+    }
+
+    // Closing the gap makes the old field stale
+    for y in 15..20 {
+      map.set_building_at(12, y, Wall::new(0, 12, y, 1.0, entities.getindex()), &mut entities).unwrap();
+    }
+    assert!(flows.get(&goal, &map).path_from(&Pos(1, 1), &map).is_none());
+  }
+}
@@ -0,0 +1,306 @@
+use super::{Pos, STEP_COST, getmapvecidx};
+use pathfinding::prelude::{astar, dijkstra_all};
+use std::collections::HashMap;
+
+pub const CLUSTER_SIZE: i32 = 16; // Side length of a cluster in tiles
+const WIDE_ENTRANCE: usize = 6;   // Border openings this wide get an entrance at each end instead of one in the middle
+
+/// Hierarchical pathfinder (HPA*). The map is cut into square clusters, with entrance tiles
+/// wherever two clusters share an open border. Long paths are searched over the entrances first,
+/// then filled in one cluster at a time, so a search never has to flood the whole costmap
+#[derive(Clone)]
+pub struct Hpa {
+  width: i32,
+  height: i32,
+  cw: i32, // Clusters across
+  ch: i32, // Clusters down
+  entrances: HashMap<(usize, usize), Vec<(Pos, Pos)>>, // Tile pairs across the border of two clusters, first tile in the first cluster
+  intra: Vec<HashMap<Pos, Vec<(Pos, usize)>>>,          // Per cluster, cost from each entrance to the others it can reach inside the cluster
+}
+
+impl Hpa {
+  pub fn new(costmap: &[usize], width: i32, height: i32) -> Self {
+    let cw = (width + CLUSTER_SIZE - 1) / CLUSTER_SIZE;
+    let ch = (height + CLUSTER_SIZE - 1) / CLUSTER_SIZE;
+    let mut hpa = Hpa { width, height, cw, ch, entrances: HashMap::new(), intra: Vec::new() };
+    hpa.intra.resize_with((cw * ch) as usize, HashMap::new);
+    for c in 0..(cw * ch) as usize {
+      hpa.build_borders(c, costmap);
+    }
+    for c in 0..(cw * ch) as usize {
+      hpa.build_intra(c, costmap);
+    }
+    hpa
+  }
+
+  pub fn cluster_of(&self, x: i32, y: i32) -> usize {
+    ((x / CLUSTER_SIZE) + ((y / CLUSTER_SIZE) * self.cw)) as usize
+  }
+
+  /// Tile bounds of a cluster, max exclusive
+  fn bounds(&self, c: usize) -> (i32, i32, i32, i32) {
+    let x0 = (c as i32 % self.cw) * CLUSTER_SIZE;
+    let y0 = (c as i32 / self.cw) * CLUSTER_SIZE;
+    (x0, y0, (x0 + CLUSTER_SIZE).min(self.width), (y0 + CLUSTER_SIZE).min(self.height))
+  }
+
+  /// Border keys of a cluster: east and south are (c, neighbour), west and north are (neighbour, c)
+  fn border_keys(&self, c: usize) -> Vec<(usize, usize)> {
+    let (cx, cy) = (c as i32 % self.cw, c as i32 / self.cw);
+    let mut keys = Vec::new();
+    if cx < self.cw - 1 { keys.push((c, c + 1)); }
+    if cy < self.ch - 1 { keys.push((c, c + self.cw as usize)); }
+    if cx > 0 { keys.push((c - 1, c)); }
+    if cy > 0 { keys.push((c - self.cw as usize, c)); }
+    keys
+  }
+
+  /// Find the entrances on the east and south borders of a cluster
+  fn build_borders(&mut self, c: usize, costmap: &[usize]) {
+    let (x0, y0, x1, y1) = self.bounds(c);
+    if x1 < self.width {
+      let pairs: Vec<(Pos, Pos)> = (y0..y1).map(|y| (Pos(x1 - 1, y), Pos(x1, y))).collect();
+      let e = self.pick_entrances(pairs, costmap);
+      self.entrances.insert((c, c + 1), e);
+    }
+    if y1 < self.height {
+      let pairs: Vec<(Pos, Pos)> = (x0..x1).map(|x| (Pos(x, y1 - 1), Pos(x, y1))).collect();
+      let e = self.pick_entrances(pairs, costmap);
+      self.entrances.insert((c, c + self.cw as usize), e);
+    }
+  }
+
+  /// Split a border into runs where both sides are open, and keep one or two crossings per run
+  fn pick_entrances(&self, pairs: Vec<(Pos, Pos)>, costmap: &[usize]) -> Vec<(Pos, Pos)> {
+    let mut ret = Vec::new();
+    let mut run: Vec<(Pos, Pos)> = Vec::new();
+    for (a, b) in pairs.into_iter().chain(Some((Pos(-1, -1), Pos(-1, -1)))) { // Sentinel closes the last run
+      if self.open(&a, costmap) && self.open(&b, costmap) {
+        run.push((a, b));
+        continue;
+      }
+      if run.len() >= WIDE_ENTRANCE {
+        ret.push(run[0].clone());
+        ret.push(run[run.len() - 1].clone());
+      } else if !run.is_empty() {
+        ret.push(run[run.len() / 2].clone());
+      }
+      run.clear();
+    }
+    ret
+  }
+
+  fn open(&self, p: &Pos, costmap: &[usize]) -> bool {
+    p.0 >= 0 && p.0 < self.width && p.1 >= 0 && p.1 < self.height &&
+      costmap[getmapvecidx(p.0, p.1, self.width)] < usize::MAX
+  }
+
+  /// Entrance tiles inside a cluster
+  fn nodes(&self, c: usize) -> Vec<Pos> {
+    let mut nodes = Vec::new();
+    for k in self.border_keys(c) {
+      for (a, b) in self.entrances.get(&k).into_iter().flatten() {
+        let p = if k.0 == c { a } else { b };
+        if !nodes.contains(p) { nodes.push(p.clone()); }
+      }
+    }
+    nodes
+  }
+
+  /// Entrance tiles in neighbouring clusters that p steps straight across to
+  fn links(&self, p: &Pos) -> Vec<Pos> {
+    let c = self.cluster_of(p.0, p.1);
+    let mut ret = Vec::new();
+    for k in self.border_keys(c) {
+      for (a, b) in self.entrances.get(&k).into_iter().flatten() {
+        if a == p { ret.push(b.clone()); }
+        if b == p { ret.push(a.clone()); }
+      }
+    }
+    ret
+  }
+
+  fn build_intra(&mut self, c: usize, costmap: &[usize]) {
+    let nodes = self.nodes(c);
+    let mut edges = HashMap::new();
+    for n in nodes.iter() {
+      let reached = dijkstra_all(n, |p| self.local_successors(c, p, costmap));
+      let e: Vec<(Pos, usize)> = nodes.iter()
+        .filter_map(|m| reached.get(m).map(|(_, cost)| (m.clone(), *cost)))
+        .collect();
+      edges.insert(n.clone(), e);
+    }
+    self.intra[c] = edges;
+  }
+
+  fn local_successors(&self, c: usize, p: &Pos, costmap: &[usize]) -> Vec<(Pos, usize)> {
+    p.successors(costmap, self.width, self.height).into_iter()
+      .filter(|(q, _)| self.cluster_of(q.0, q.1) == c)
+      .collect()
+  }
+
+  /// A* that never leaves cluster c
+  fn local_path(&self, c: usize, from: &Pos, to: &Pos, costmap: &[usize]) -> Option<(Vec<Pos>, usize)> {
+    astar(from, |p| self.local_successors(c, p, costmap), |p| p.octile(to), |p| p == to)
+  }
+
+  /// The costmap changed at (x, y). Only that cluster's entrance costs are redone, plus its
+  /// neighbours' when the tile sits on a border and so might open or close an entrance
+  pub fn update(&mut self, x: i32, y: i32, costmap: &[usize]) {
+    if x < 0 || x >= self.width || y < 0 || y >= self.height { return; }
+    let c = self.cluster_of(x, y);
+    let (x0, y0, x1, y1) = self.bounds(c);
+    if x == x0 || x == x1 - 1 || y == y0 || y == y1 - 1 {
+      let keys = self.border_keys(c);
+      for (a, _) in keys.iter().filter(|k| k.1 == c) {
+        self.rebuild_border_of(*a, c, costmap);
+      }
+      self.build_borders(c, costmap);
+      for (a, b) in keys {
+        self.build_intra(if a == c { b } else { a }, costmap);
+      }
+    }
+    self.build_intra(c, costmap);
+  }
+
+  /// Redo only the border between a and the cluster after it, leaving a's other border alone
+  fn rebuild_border_of(&mut self, a: usize, b: usize, costmap: &[usize]) {
+    let (x0, y0, x1, y1) = self.bounds(a);
+    let pairs: Vec<(Pos, Pos)> = if b == a + 1 && self.cw > 1 {
+      (y0..y1).map(|y| (Pos(x1 - 1, y), Pos(x1, y))).collect()
+    } else {
+      (x0..x1).map(|x| (Pos(x, y1 - 1), Pos(x, y1))).collect()
+    };
+    let e = self.pick_entrances(pairs, costmap);
+    self.entrances.insert((a, b), e);
+  }
+
+  /// Tile path from one tile to another, or None if there is no way there
+  pub fn getpath(&self, from: &Pos, to: &Pos, costmap: &[usize]) -> Option<Vec<Pos>> {
+    let cs = self.cluster_of(from.0, from.1);
+    let cg = self.cluster_of(to.0, to.1);
+    if cs == cg {
+      if let Some((path, _)) = self.local_path(cs, from, to, costmap) { return Some(path); }
+    }
+
+    // Hook the start and goal into the entrance graph of their clusters
+    let reached = dijkstra_all(from, |p| self.local_successors(cs, p, costmap));
+    let startedges: Vec<(Pos, usize)> = self.nodes(cs).into_iter()
+      .filter_map(|n| if n == *from { Some((n, 0)) } else { reached.get(&n).map(|(_, cost)| (n.clone(), *cost)) })
+      .collect();
+    let goalcosts: HashMap<Pos, usize> = self.nodes(cg).into_iter()
+      .filter_map(|n| self.local_path(cg, &n, to, costmap).map(|(_, cost)| (n, cost)))
+      .collect();
+    if startedges.is_empty() || goalcosts.is_empty() { return None; }
+
+    let (route, _) = astar(from, |p| {
+      let mut next: Vec<(Pos, usize)> = Vec::new();
+      if p == from {
+        next.extend(startedges.iter().cloned());
+      } else if let Some(e) = self.intra[self.cluster_of(p.0, p.1)].get(p) {
+        next.extend(e.iter().cloned());
+      }
+      for q in self.links(p) {
+        let cost = costmap[getmapvecidx(q.0, q.1, self.width)] * STEP_COST;
+        next.push((q, cost));
+      }
+      if let Some(cost) = goalcosts.get(p) { next.push((to.clone(), *cost)); }
+      next
+    }, |p| p.octile(to), |p| p == to)?;
+
+    // Fill in the tiles between entrances
+    let mut path = vec![from.clone()];
+    for w in route.windows(2) {
+      let (a, b) = (&w[0], &w[1]);
+      let c = self.cluster_of(a.0, a.1);
+      if a == b { continue; }
+      if c != self.cluster_of(b.0, b.1) {
+        path.push(b.clone()); // Step across a border
+      } else {
+        let (seg, _) = self.local_path(c, a, b, costmap)?;
+        path.extend(seg.into_iter().skip(1));
+      }
+    }
+    Some(path)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{Map, test_map};
+  use crate::tiles::TileRegistry;
+  use super::super::super::entities::{Entities, Wall};
+  use std::time::Instant;
+
+  fn check_path(map: &Map, path: &[Pos], from: &Pos, to: &Pos) {
+    assert_eq!(path.first(), Some(from));
+    assert_eq!(path.last(), Some(to));
+    for w in path.windows(2) {
+      assert!(w[0].octile(&w[1]) <= super::super::DIAGONAL_COST);
+      assert!(map.ispassable(w[1].0, w[1].1));
+    }
+  }
+
+  #[test]
+  fn test_matches_flat_reachability() {
+    let tiles = TileRegistry::standard();
+    let map = Map::new(&tiles, 5, 80, 64);
+    let pts: Vec<Pos> = (0..20).map(|i| Pos((i * 37) % 80, (i * 23) % 64))
+      .filter(|p| map.ispassable(p.0, p.1)).collect();
+    assert!(pts.len() > 4);
+    for from in pts.iter() {
+      for to in pts.iter() {
+        let hpa = map.getpath(from.clone(), to.clone());
+        let flat = map.getpath_flat(from.clone(), to.clone());
+        assert_eq!(hpa.is_ok(), flat.is_ok());
+        if let Ok(path) = hpa { check_path(&map, &path, from, to); }
+      }
+    }
+  }
+
+  #[test]
+  fn test_walls_update_clusters() {
+    let mut map = test_map(48, 20);
+    let mut entities = Entities::new();
+    // Wall off the whole column on a cluster border, then open one gap in it
+    for y in 0..20 {
+      map.set_building_at(16, y, Wall::new(0, 16, y, 1.0, entities.getindex()), &mut entities).unwrap();
+    }
+    assert!(map.getpath(Pos(2, 2), Pos(40, 2)).is_err());
+    map.clear_building_at(16, 13, &mut entities).unwrap();
+    let path = map.getpath(Pos(2, 2), Pos(40, 2)).unwrap();
+    check_path(&map, &path, &Pos(2, 2), &Pos(40, 2));
+    assert!(path.contains(&Pos(16, 13)));
+  }
+
+  /// cargo test --release -- --ignored --nocapture bench_hpa_vs_flat
+  #[test]
+  #[ignore]
+  fn bench_hpa_vs_flat() {
+    let tiles = TileRegistry::standard();
+    let t = Instant::now();
+    let mut map = Map::new(&tiles, 1234, 300, 300);
+    println!("map and clusters built in {:?}", t.elapsed());
+    let pts: Vec<Pos> = (0..40).map(|i| Pos(20 + (i * 53) % 260, 20 + (i * 97) % 260))
+      .filter(|p| map.ispassable(p.0, p.1)).collect();
+
+    let t = Instant::now();
+    let mut found = 0;
+    for w in pts.windows(2) {
+      if map.getpath_flat(w[0].clone(), w[1].clone()).is_ok() { found = found + 1; }
+    }
+    println!("flat A*: {} paths in {:?}", found, t.elapsed());
+
+    let t = Instant::now();
+    let mut found = 0;
+    for w in pts.windows(2) {
+      if map.getpath(w[0].clone(), w[1].clone()).is_ok() { found = found + 1; }
+    }
+    println!("HPA*:    {} paths in {:?}", found, t.elapsed());
+
+    let t = Instant::now();
+    std::sync::Arc::make_mut(&mut map.hpa).update(150, 150, &map.costmap);
+    println!("one cluster update in {:?}", t.elapsed());
+  }
+}
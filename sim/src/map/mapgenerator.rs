@@ -0,0 +1,239 @@
+use super::*;
+use crate::plants::{Plant, PlantKind, STAGES, scatter};
+
+const TREE_DENSITY: f32 = 0.35;  // Chance of a tree on the wettest grass
+const SHRUB_DENSITY: f32 = 0.15; // Chance of a shrub on the driest
+
+pub struct MapGenerator {
+
+}
+
+enum TileType {
+  DeepWater,
+  Water,
+  Sand,
+  Grass,
+  Rock,
+}
+
+impl TileType {
+  /// Terrain height order. Tiles blend their edges into neighbours of a lower tier
+  fn tier(&self) -> u8 {
+    match self {
+      TileType::DeepWater => 0,
+      TileType::Water => 1,
+      TileType::Sand => 2,
+      TileType::Grass => 3,
+      TileType::Rock => 4,
+    }
+  }
+}
+
+impl MapGenerator {
+
+  fn island_mask(val: f64, x: i32, y: i32, width: i32, height: i32) -> f64 {
+    let x = x as f64;
+    let y = y as f64;
+    let x = (x - width as f64 * 0.5).abs();
+    let y = (y - height as f64 * 0.5).abs();
+    let x = x / (width as f64 * 0.5);
+    let y = y / (height as f64 * 0.5);
+    let mut d = x;
+    if d < y { d = y; }
+
+    (1.0 - d) * val
+  }
+
+  fn generate_tile(e: f64) -> TileType {
+    match e {
+      e if e < 0.03 => TileType::DeepWater,
+      0.03..=0.08 => TileType::Water, // Water
+      0.08..=0.2 => TileType::Sand, // Grass
+      0.5..=0.8 => TileType::Grass,  // Grass
+      e if e > 0.8 => TileType::Rock, // Mountains
+      _ => TileType::Grass,
+    }
+  }
+
+  pub fn generate_map(seed: u32, width: i32, height: i32, tiles: &TileRegistry) -> Map {
+
+    let mut tilemap: Vec<(Tile, u32)> = Vec::new();
+    let mut build_layer = Vec::new();
+    let mut costmap = Vec::new();
+    let mut tiers: Vec<u8> = Vec::new();
+    let mut plants = HashMap::new();
+
+    let elevation = Perlin::new();
+    let elevation = elevation.set_seed(seed);
+    let moisture = Perlin::new();
+    let moisture = moisture.set_seed(seed.wrapping_add(1));
+
+
+    for y in 0..height { // Tile gen, row by row so the vec index is x + (y * width)
+      for x in 0..width {
+        let mut val: f64 = elevation.get([x as f64 * NOISESCALE, y as f64 * NOISESCALE]);
+        val = val + (0.5 * elevation.get([x as f64 * (2.0 * NOISESCALE), y as f64 * (2.0 * NOISESCALE)]));
+        val = val + (0.25 * elevation.get([x as f64 * (4.0 * NOISESCALE), y as f64 * (4.0 * NOISESCALE)]));
+        val = val + 1.0;
+        val = val * 0.5;  // Now between 0...1
+        
+        let mut m: f64 = moisture.get([x as f64 * NOISESCALE, y as f64 * NOISESCALE]);
+        m = m + (0.5 * moisture.get([x as f64 * (2.0 * NOISESCALE), y as f64 * (2.0 * NOISESCALE)]));
+        m = m + (0.25 * moisture.get([x as f64 * (4.0 * NOISESCALE), y as f64 * (4.0 * NOISESCALE)]));
+        m = m + 1.0;
+        m = m * 0.5;
+        m = m.clamp(0.0, 1.0); // Octaves can overshoot, and every variant match below expects 0...1
+
+        m = m.powf(1.3);
+        
+        val = MapGenerator::island_mask(val, x, y, width, height);
+        let t = MapGenerator::generate_tile(val);
+        tiers.push(t.tier());
+        let grass = matches!(t, TileType::Grass);
+        let name = match t {
+          TileType::Water => if m <= 0.5 { "water0" } else { "water1" },
+          TileType::DeepWater => if m <= 0.5 { "deepwater0" } else { "deepwater1" },
+          TileType::Sand => if m <= 0.5 { "drysand" } else { "dirt" },
+          TileType::Grass => {
+            match m {
+              0.0..=0.2 => "grass0",
+              0.2..=0.35 => "grass1",
+              0.35..=0.55 => "grass2",
+              0.55..=0.75 => "grass3",
+              0.75..=0.9 => "grass4",
+              _ => "grass5",
+            }
+          }
+          TileType::Rock => {
+            match m {
+              0.0..=0.3 => "rock0",
+              0.3..=0.6 => "rock1",
+              _ => "rock2",
+            }
+          }
+        };
+        let id = tiles.get_id(name).expect("tile type missing from the registry");
+        let cost = tiles.get(id).unwrap().movecost;
+        let mut t = Tile::new(id, x, y, 1.0);
+        t.setmovecost(cost);
+        let alt: u32 = 0;
+
+        // Trees where the grass is wet, shrubs where it's dry, thicker the further either way
+        let roll = scatter(seed, x, y);
+        let plant = match grass {
+          true if m > 0.55 && roll < TREE_DENSITY * ((m as f32 - 0.55) / 0.45) => Some(PlantKind::Tree),
+          true if m < 0.35 && roll < SHRUB_DENSITY * ((0.35 - m as f32) / 0.35) => Some(PlantKind::Shrub),
+          _ => None,
+        };
+        let mut cost = cost;
+        if let Some(kind) = plant {
+          let p = Plant::new(kind, STAGES - 1); // Wild plants start out grown
+          cost = cost.max(p.movecost());
+          plants.insert((x, y), p);
+        }
+
+        costmap.push(cost);
+        tilemap.push((t, alt));
+        build_layer.push(None);
+      }
+    }
+
+    // Edges pass
+    for y in 0..height {
+      for x in 0..width {
+        let idx = getmapvecidx(x, y, width);
+        let tier = tiers[idx];
+
+        let mut edges: u8 = 0;
+        if y > 0 && tiers[getmapvecidx(x, y-1, width)] < tier { edges |= EDGE_NORTH; }
+        if x < (width - 1) && tiers[getmapvecidx(x+1, y, width)] < tier { edges |= EDGE_EAST; }
+        if y < (height - 1) && tiers[getmapvecidx(x, y+1, width)] < tier { edges |= EDGE_SOUTH; }
+        if x > 0 && tiers[getmapvecidx(x-1, y, width)] < tier { edges |= EDGE_WEST; }
+
+        let (t, alt) = tilemap.get_mut(idx).unwrap();
+        t.setedges(edges);
+        // Alternate n of a blending tile is its transition sprite for edge mask n
+        if edges > 0 && tiles.get(t.id).is_some_and(|d| d.blends) {
+          t.setalternate(&(edges as u32));
+          *alt = edges as u32;
+        }
+      }
+    }
+
+    let mut map = Map::from_layers(tilemap, build_layer, costmap, seed, width, height);
+    map.plants = plants;
+    map
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::entities::Entity;
+
+  #[test]
+  fn test_same_seed_same_map() {
+    let tiles = TileRegistry::standard();
+    let a = MapGenerator::generate_map(1234, 300, 300, &tiles);
+    let b = MapGenerator::generate_map(1234, 300, 300, &tiles);
+
+    let atiles: Vec<(u32, u32, usize)> = a.tilemap.iter().map(|(t, alt)| (t.id, *alt, t.getmovecost())).collect();
+    let btiles: Vec<(u32, u32, usize)> = b.tilemap.iter().map(|(t, alt)| (t.id, *alt, t.getmovecost())).collect();
+    assert_eq!(atiles, btiles);
+    assert_eq!(a.costmap, b.costmap);
+
+    let c = MapGenerator::generate_map(4321, 300, 300, &tiles);
+    assert_ne!(a.costmap, c.costmap);
+  }
+
+  #[test]
+  fn test_nonsquare_layout() {
+    let tiles = TileRegistry::standard();
+    let map = MapGenerator::generate_map(99, 60, 25, &tiles);
+    assert_eq!(map.tilemap.len(), 60 * 25);
+    assert_eq!(map.costmap.len(), 60 * 25);
+    for (i, (t, _)) in map.tilemap.iter().enumerate() {
+      let (x, y) = t.getoccupiedtile();
+      assert_eq!(map.getmapvecidx(x, y), i);
+    }
+  }
+
+  #[test]
+  fn test_plants_follow_moisture() {
+    let tiles = TileRegistry::standard();
+    let map = MapGenerator::generate_map(1234, 120, 80, &tiles);
+    let kinds: Vec<PlantKind> = map.getplants().values().map(|p| p.kind).collect();
+    assert!(kinds.contains(&PlantKind::Tree));
+    assert!(kinds.contains(&PlantKind::Shrub));
+    for ((x, y), p) in map.getplants().iter() {
+      let name = &tiles.get(map.get_tile_at(*x, *y).unwrap().0.id).unwrap().name;
+      let wet = ["grass3", "grass4", "grass5"].contains(&name.as_str());
+      let dry = ["grass0", "grass1"].contains(&name.as_str());
+      assert!(if p.kind == PlantKind::Tree { wet } else { dry });
+      assert_eq!(map.costmap[map.getmapvecidx(*x, *y)], p.movecost().max(1)); // Grass costs 1
+    }
+  }
+
+  #[test]
+  fn test_edges_face_lower_terrain() {
+    let tiles = TileRegistry::standard();
+    let map = MapGenerator::generate_map(1234, 120, 80, &tiles);
+    let sand = [tiles.get_id("drysand").unwrap(), tiles.get_id("dirt").unwrap()];
+    let water: Vec<u32> = ["water0", "water1", "deepwater0", "deepwater1"].iter()
+      .map(|n| tiles.get_id(n).unwrap()).collect();
+
+    let mut shore = 0;
+    for y in 1..(map.height - 1) {
+      for x in 1..(map.width - 1) {
+        let (t, alt) = map.get_tile_at(x, y).unwrap();
+        if !sand.contains(&t.id) { continue; }
+        assert_eq!(*alt, t.getedges() as u32); // Sand blends into the water
+        let east = map.get_tile_at(x + 1, y).unwrap().0.id;
+        assert_eq!(water.contains(&east), t.getedges() & EDGE_EAST != 0);
+        if t.getedges() != 0 { shore += 1; }
+      }
+    }
+    assert!(shore > 0);
+  }
+}
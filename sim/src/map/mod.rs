@@ -0,0 +1,486 @@
+use crate::entities::{ BuildableEntity, Buildable, Entities, Tile};
+use crate::error::{SimError, SimResult};
+use crate::tiles::TileRegistry;
+use crate::items::{ItemKind, Stack};
+use crate::plants::Plant;
+use noise::{ NoiseFn, Perlin };
+use noise::Seedable;
+use pathfinding::prelude::{absdiff, astar};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+mod mapgenerator;
+pub mod hpa;
+pub mod regions;
+pub mod pathservice;
+pub mod flowfield;
+use self::mapgenerator::MapGenerator;
+use self::hpa::Hpa;
+use self::regions::Regions;
+
+const NOISESCALE: f64 = 0.05;
+
+// Path step weights, multiplied by the tile cost. 14/10 is close enough to sqrt(2)
+pub const STEP_COST: usize = 10;
+pub const DIAGONAL_COST: usize = 14;
+
+// Tile edge flags, set where the neighbour on that side is lower terrain
+pub const EDGE_NORTH: u8 = 1;
+pub const EDGE_EAST: u8 = 2;
+pub const EDGE_SOUTH: u8 = 4;
+pub const EDGE_WEST: u8 = 8;
+
+pub struct Map {
+  pub tilemap: Vec<(Tile, u32)>,
+  pub build_layer: Vec<Option<Box<dyn Buildable>>>,
+  pub costmap: Arc<Vec<usize>>, // Shared with path searches still running, so it is copied on write
+  pub items: HashMap<(i32, i32), Stack>, // Loose and stockpiled items, one stack per tile at most
+  pub plants: HashMap<(i32, i32), Plant>, // Trees and shrubs, they add to the cost of their tile
+  pub seed: u32,
+  pub width: i32,
+  pub height: i32,
+  pub hpa: Arc<Hpa>,    // hpa and regions are kept in step with costmap,
+  pub regions: Regions, // so every costmap change goes through refresh_cost_at
+  pub version: u64,     // Bumped on every costmap change, so path results from an older map can be told apart
+  changes: Vec<(i32, i32)>, // Tiles whose cost changed since the last take_changes
+}
+
+impl Map {
+  /// Generate a new map. The same seed always gives the same map
+  pub fn new(tiles: &TileRegistry, seed: u32, width: i32, height: i32) -> Self {
+    MapGenerator::generate_map(seed, width, height, tiles)
+  }
+
+  /// Put a map together from its layers, building the pathfinding data from the costmap
+  pub fn from_layers(tilemap: Vec<(Tile, u32)>, build_layer: Vec<Option<Box<dyn Buildable>>>, costmap: Vec<usize>, seed: u32, width: i32, height: i32) -> Self {
+    let hpa = Arc::new(Hpa::new(&costmap, width, height));
+    let regions = Regions::new(&costmap, width, height);
+    Map { tilemap, build_layer, costmap: Arc::new(costmap), items: HashMap::new(), plants: HashMap::new(), seed, width, height, hpa, regions, version: 0, changes: Vec::new() }
+  }
+
+  pub fn getpath(&self, from: Pos, to: Pos) -> SimResult<Vec<Pos>> {
+    if !self.regions.is_reachable(&from, &to) {
+      return Err(SimError::NoPath);
+    }
+    match self.hpa.getpath(&from, &to, &self.costmap) {
+      Some(result) => { Ok(result) },
+      None => { Err(SimError::NoPath) },
+    }
+  }
+
+  /// Plain A* over the whole costmap. Shortest path, but slow across big maps
+  pub fn getpath_flat(&self, from: Pos, to: Pos) -> SimResult<Vec<Pos>> {
+    let (w, h) = (self.width, self.height);
+    let result = astar(&from, |p| p.successors(&self.costmap, w, h), |p| p.octile(&to), |p| *p == to);
+    match result {
+      Some((result, _)) => { Ok(result) },
+      None => { Err(SimError::NoPath) },
+    }
+  }
+
+  /// Path to any tile next to `to`, for work done on a tile rather than standing in it
+  pub fn getpath_adjacent(&self, from: Pos, to: Pos) -> SimResult<Vec<Pos>> {
+    if !self.is_reachable_adjacent(&from, &to) {
+      return Err(SimError::NoPath);
+    }
+    path_adjacent(&self.costmap, self.width, self.height, &from, &to).ok_or(SimError::NoPath)
+  }
+
+  pub fn get_tile_at(&self, x: i32, y: i32) -> SimResult<&(Tile, u32)> {
+    if !self.check_bounds(x, y) {
+      return Err(SimError::OutOfBounds);
+    }
+    Ok(self.tilemap.get(self.getmapvecidx(x, y)).unwrap())
+  }
+
+  pub fn set_tile_at(&mut self, x: i32, y: i32, t: Tile) -> SimResult<()> {
+    if !self.check_bounds(x, y) {
+         return Err(SimError::OutOfBounds);
+    }
+    let idx = self.getmapvecidx(x, y);
+    self.tilemap.remove(idx);
+    let a = &t.alt.clone();
+    self.tilemap.insert(idx, (t, *a));
+    Ok(())
+
+  }
+
+  pub fn get_building_at(&mut self, x: i32, y: i32) -> bool {
+    if !self.check_bounds(x, y) {
+      return false;
+    }
+    match self.build_layer.get(self.getmapvecidx(x, y)).unwrap() {
+      Some(_) => { true }
+      None    => { false }
+    }
+  }
+
+  pub fn get_building(&self, x: i32, y: i32) -> Option<&dyn Buildable> {
+    if !self.check_bounds(x, y) {
+      return None;
+    }
+    self.build_layer[self.getmapvecidx(x, y)].as_deref()
+  }
+
+  pub fn set_building_at<T: BuildableEntity + Copy + 'static>(&mut self, x: i32, y: i32, w: T, entities: &mut Entities) -> SimResult<()> {
+    if !self.check_bounds(x, y) {
+      return Err(SimError::OutOfBounds);
+    }
+    let idx = self.getmapvecidx(x, y);
+    if self.build_layer[idx].is_some() {
+      return Err(SimError::Occupied); // Clear it first, or the old building is left behind in entities
+    }
+    let a = Box::new(w);
+    entities.add_building(w);
+    self.build_layer.remove(idx);
+    self.build_layer.insert(idx, Some(a));
+    self.refresh_cost_at(x, y);
+    Ok(())
+  }
+
+  /// Swap whatever is built on a tile for `w` in one go, e.g. a finished blueprint for the building.
+  /// Only fails out of bounds, before anything has changed
+  pub fn replace_building_at<T: BuildableEntity + Copy + 'static>(&mut self, x: i32, y: i32, w: T, entities: &mut Entities) -> SimResult<()> {
+    if !self.check_bounds(x, y) {
+      return Err(SimError::OutOfBounds);
+    }
+    let idx = self.getmapvecidx(x, y);
+    if let Some(old) = self.build_layer[idx].take() {
+      entities.remove_building(old.getentityid());
+    }
+    self.set_building_at(x, y, w, entities)
+  }
+
+  /// Recompute a tile's path cost from its terrain and whatever is built on it
+  pub fn refresh_cost_at(&mut self, x: i32, y: i32) {
+    if !self.check_bounds(x, y) { return; }
+    let idx = self.getmapvecidx(x, y);
+    let mut tilecost = self.tilemap[idx].0.getmovecost();
+    if let Some(p) = self.plants.get(&(x, y)) { tilecost = tilecost.max(p.movecost()); }
+    // Buildings can only make a tile harder to cross, e.g. a blueprint leaves the terrain cost alone
+    let cost = match &self.build_layer[idx] {
+      Some(b) if !b.iscrossable() => usize::MAX,
+      Some(b) => tilecost.max(b.getmovecost()),
+      None => tilecost,
+    };
+    if self.costmap[idx] != cost {
+      // Only copies if a path search still has the old map
+      Arc::make_mut(&mut self.costmap)[idx] = cost;
+      Arc::make_mut(&mut self.hpa).update(x, y, &self.costmap);
+      self.regions.update(x, y, &self.costmap);
+      self.version = self.version + 1;
+      self.changes.push((x, y));
+    }
+  }
+
+  /// Tiles whose path cost changed since the last call, for anything that has to react to them
+  pub fn take_changes(&mut self) -> Vec<(i32, i32)> {
+    std::mem::take(&mut self.changes)
+  }
+
+  /// True if some path leads from one tile to the other, without searching for it
+  pub fn is_reachable(&self, from: &Pos, to: &Pos) -> bool {
+    self.regions.is_reachable(from, to)
+  }
+
+  /// True if some path leads from `from` to a tile next to `to`
+  pub fn is_reachable_adjacent(&self, from: &Pos, to: &Pos) -> bool {
+    let Pos(x, y) = *to;
+    [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)].iter().any(|(a, b)| self.regions.is_reachable(from, &Pos(*a, *b)))
+  }
+
+  /// Lock or unlock the door on a tile. Returns false if there is no door there
+  pub fn set_locked_at(&mut self, x: i32, y: i32, locked: bool, entities: &mut Entities) -> bool {
+    if !self.check_bounds(x, y) { return false; }
+    let idx = self.getmapvecidx(x, y);
+    let id = match &mut self.build_layer[idx] {
+      Some(b) if b.islockable() => {
+        b.setlocked(locked);
+        b.getentityid()
+      }
+      _ => { return false; }
+    };
+    if let Some(b) = entities.get_building_mut(id) { b.setlocked(locked); }
+    self.refresh_cost_at(x, y);
+    true
+  }
+
+  pub fn clear_building_at(&mut self, x: i32, y: i32, entities: &mut Entities) -> SimResult<()> {
+    if !self.check_bounds(x, y) {
+      return Err(SimError::OutOfBounds);
+    }
+    let idx = self.getmapvecidx(x, y);
+    let w = self.build_layer.remove(idx);
+    self.build_layer.insert(idx, None);
+    if let Some(b) = w {
+      entities.remove_building(b.getentityid());
+    }
+    self.refresh_cost_at(x, y);
+    Ok(())
+  }
+
+  /// Shortest walk (in steps) from `from` to the nearest tile that isn't `taken`, never passing
+  /// through `blocked` tiles or going more than `maxsteps` out. Used to step aside for others
+  pub fn path_aside<B, T>(&self, from: &Pos, blocked: B, taken: T, maxsteps: usize) -> Option<Vec<Pos>>
+    where B: Fn(&Pos) -> bool, T: Fn(&Pos) -> bool {
+    let mut parents: HashMap<Pos, Pos> = HashMap::new();
+    let mut frontier = VecDeque::new();
+    frontier.push_back((from.clone(), 0));
+    parents.insert(from.clone(), from.clone());
+    while let Some((p, d)) = frontier.pop_front() {
+      if p != *from && !taken(&p) {
+        let mut path = vec![p.clone()];
+        let mut c = p;
+        while c != *from {
+          c = parents[&c].clone();
+          path.push(c.clone());
+        }
+        path.reverse();
+        return Some(path);
+      }
+      if d >= maxsteps { continue; }
+      for (n, _) in p.successors(&self.costmap, self.width, self.height) {
+        if parents.contains_key(&n) || blocked(&n) { continue; }
+        parents.insert(n.clone(), p.clone());
+        frontier.push_back((n, d + 1));
+      }
+    }
+    None
+  }
+
+  /// Closest tile to (x, y) that can be walked on, searching outwards ring by ring
+  pub fn nearest_passable(&self, x: i32, y: i32) -> Option<(i32, i32)> {
+    let maxr = self.width.max(self.height);
+    for r in 0..maxr {
+      for dy in -r..=r {
+        for dx in -r..=r {
+          if dx.abs() != r && dy.abs() != r { continue; } // Only the ring itself
+          let (a, b) = (x + dx, y + dy);
+          if self.ispassable(a, b) {
+            return Some((a, b));
+          }
+        }
+      }
+    }
+    None
+  }
+
+  pub fn getitems(&self) -> &HashMap<(i32, i32), Stack> { &self.items }
+
+  pub fn get_items_at(&self, x: i32, y: i32) -> Option<&Stack> { self.items.get(&(x, y)) }
+
+  /// How many of `kind` could still go on a tile
+  pub fn room_at(&self, x: i32, y: i32, kind: ItemKind) -> u32 {
+    if !self.ispassable(x, y) { return 0; }
+    match self.items.get(&(x, y)) {
+      Some(s) if s.kind == kind => kind.stacklimit().saturating_sub(s.count),
+      Some(_) => 0,
+      None => kind.stacklimit(),
+    }
+  }
+
+  /// Put up to `count` of `kind` on a tile, returns how many didn't fit
+  pub fn add_items_at(&mut self, x: i32, y: i32, kind: ItemKind, count: u32) -> u32 {
+    let n = count.min(self.room_at(x, y, kind));
+    if n > 0 { self.items.entry((x, y)).or_insert(Stack::new(kind, 0)).count += n; }
+    count - n
+  }
+
+  /// Take up to `count` of `kind` off a tile, returns how many were taken
+  pub fn take_items_at(&mut self, x: i32, y: i32, kind: ItemKind, count: u32) -> u32 {
+    let (n, empty) = match self.items.get_mut(&(x, y)) {
+      Some(s) if s.kind == kind => {
+        let n = count.min(s.count);
+        s.count = s.count - n;
+        (n, s.count == 0)
+      }
+      _ => (0, false),
+    };
+    if empty { self.items.remove(&(x, y)); }
+    n
+  }
+
+  /// Put items down on (x, y), spilling onto the nearest tiles with room once it's full
+  pub fn drop_items(&mut self, x: i32, y: i32, kind: ItemKind, count: u32) {
+    let mut left = count;
+    let maxr = self.width.max(self.height);
+    for r in 0..maxr {
+      for dy in -r..=r {
+        for dx in -r..=r {
+          if dx.abs() != r && dy.abs() != r { continue; }
+          left = self.add_items_at(x + dx, y + dy, kind, left);
+          if left == 0 { return; }
+        }
+      }
+    }
+  }
+
+  pub fn getplants(&self) -> &HashMap<(i32, i32), Plant> { &self.plants }
+
+  pub fn get_plant_at(&self, x: i32, y: i32) -> Option<&Plant> { self.plants.get(&(x, y)) }
+
+  /// Put a plant on a tile, replacing whatever grew there
+  pub fn set_plant_at(&mut self, x: i32, y: i32, p: Plant) {
+    if !self.check_bounds(x, y) { return; }
+    self.plants.insert((x, y), p);
+    self.refresh_cost_at(x, y);
+  }
+
+  /// Clear a tile of its plant, e.g. when it's cut down
+  pub fn take_plant_at(&mut self, x: i32, y: i32) -> Option<Plant> {
+    let p = self.plants.remove(&(x, y));
+    if p.is_some() { self.refresh_cost_at(x, y); }
+    p
+  }
+
+  pub fn ispassable(&self, x: i32, y: i32) -> bool {
+    self.check_bounds(x, y) && self.costmap[self.getmapvecidx(x, y)] < usize::MAX
+  }
+
+  pub fn check_bounds(&self, x: i32, y: i32) -> bool {
+    if x < 0 || x >= self.width ||
+       y < 0 || y >= self.height {
+         return false;
+    }
+    true
+  }
+
+  /// Index into tilemap, build_layer and costmap. Rows are `width` tiles long
+  pub fn getmapvecidx(&self, x: i32, y: i32) -> usize { getmapvecidx(x, y, self.width) }
+}
+
+pub fn getmapvecidx(x: i32, y: i32, width: i32) -> usize { (x + (width * y)) as usize }
+
+/// A* from `from` to any tile next to `to`, over a costmap `width` tiles across
+pub fn path_adjacent(costmap: &[usize], width: i32, height: i32, from: &Pos, to: &Pos) -> Option<Vec<Pos>> {
+  let result = astar(from, |p| p.successors(costmap, width, height), |p| p.octile(to).saturating_sub(STEP_COST), |p| p.distance(to) == 1);
+  result.map(|(path, _)| path)
+}
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Pos(pub i32, pub i32);
+
+impl From<(i32, i32)> for Pos {
+  fn from(t: (i32, i32)) -> Pos { Pos(t.0, t.1) }
+}
+
+impl Pos { // Pathfinding is expensive :(
+  pub fn distance(&self, other: &Pos) -> usize {
+    (absdiff(self.0, other.0) + absdiff(self.1, other.1)) as usize
+  }
+  /// Cheapest possible cost of walking to other with 8-way steps, for the A* heuristic
+  pub fn octile(&self, other: &Pos) -> usize {
+    let dx = absdiff(self.0, other.0) as usize;
+    let dy = absdiff(self.1, other.1) as usize;
+    (STEP_COST * dx.max(dy)) + ((DIAGONAL_COST - STEP_COST) * dx.min(dy))
+  }
+
+  /// Walkable neighbours and the cost of stepping onto them. Diagonal steps need both
+  /// tiles they squeeze between to be walkable, so nobody cuts across a wall or water corner
+  fn successors(&self, costmap: &[usize], width: i32, height: i32) -> Vec<(Pos, usize)> {
+    let &Pos(x, y) = self;
+    let open = |x: i32, y: i32| -> Option<usize> {
+      if x < 0 || x >= width || y < 0 || y >= height { return None; }
+      let cost = costmap[getmapvecidx(x, y, width)];
+      if cost < usize::MAX { Some(cost) } else { None }
+    };
+    let mut ret: Vec<(Pos, usize)> = Vec::new();
+    for dy in -1..=1 {
+      for dx in -1..=1 {
+        if dx == 0 && dy == 0 { continue; }
+        let cost = match open(x + dx, y + dy) { Some(c) => c, None => continue };
+        if dx == 0 || dy == 0 {
+          ret.push((Pos(x + dx, y + dy), cost * STEP_COST));
+        } else if open(x + dx, y).is_some() && open(x, y + dy).is_some() {
+          ret.push((Pos(x + dx, y + dy), cost * DIAGONAL_COST));
+        }
+      }
+    }
+    ret
+  }
+  
+}
+
+/// Flat, fully walkable map for tests
+#[cfg(test)]
+pub fn test_map(width: i32, height: i32) -> Map {
+  let mut tilemap = Vec::new();
+  let mut build_layer = Vec::new();
+  for y in 0..height {
+    for x in 0..width {
+      tilemap.push((Tile::new(2, x, y, 1.0), 0));
+      build_layer.push(None);
+    }
+  }
+  let costmap = vec![1; (width * height) as usize];
+  Map::from_layers(tilemap, build_layer, costmap, 0, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::entities::{Door, Wall};
+
+  #[test]
+  fn test_successors_nonsquare() {
+    let (w, h) = (7, 3);
+    // Each tile costs its own index + 1, so the cost tells us which tile was read
+    let costmap: Vec<usize> = (1..=(w * h) as usize).collect();
+
+    let mut s = Pos(6, 1).successors(&costmap, w, h);
+    s.sort();
+    assert_eq!(s, vec![(Pos(5, 0), 6 * 14), (Pos(5, 1), 13 * 10), (Pos(5, 2), 20 * 14), (Pos(6, 0), 7 * 10), (Pos(6, 2), 21 * 10)]);
+
+    let mut s = Pos(0, 2).successors(&costmap, w, h);
+    s.sort();
+    assert_eq!(s, vec![(Pos(0, 1), 8 * 10), (Pos(1, 1), 9 * 14), (Pos(1, 2), 16 * 10)]);
+  }
+
+  #[test]
+  fn test_no_corner_cutting() {
+    let mut map = test_map(5, 5);
+    let idx = map.getmapvecidx(2, 1);
+    Arc::make_mut(&mut map.costmap)[idx] = usize::MAX;
+    let s = Pos(1, 1).successors(&map.costmap, 5, 5);
+    assert!(!s.iter().any(|(p, _)| *p == Pos(2, 0) || *p == Pos(2, 2)));
+    assert!(s.iter().any(|(p, _)| *p == Pos(0, 0)));
+
+    // Open ground goes straight down the diagonal
+    let path = test_map(10, 10).getpath(Pos(0, 0), Pos(6, 6)).unwrap();
+    assert_eq!(path.len(), 7);
+  }
+
+  #[test]
+  fn test_doors() {
+    let mut map = test_map(20, 10);
+    let mut entities = Entities::new();
+    for y in 0..10 {
+      if y == 5 {
+        map.set_building_at(10, y, Door::new(1, 10, y, 1.0, entities.getindex()), &mut entities).unwrap();
+      } else {
+        map.set_building_at(10, y, Wall::new(0, 10, y, 1.0, entities.getindex()), &mut entities).unwrap();
+      }
+    }
+    assert!(map.ispassable(10, 5));
+    assert!(map.costmap[map.getmapvecidx(10, 5)] > 1);
+    let path = map.getpath(Pos(2, 2), Pos(15, 2)).unwrap();
+    assert!(path.contains(&Pos(10, 5)));
+
+    assert!(!map.set_locked_at(10, 4, true, &mut entities)); // Walls have no lock
+    assert!(map.set_locked_at(10, 5, true, &mut entities));
+    assert!(map.getpath(Pos(2, 2), Pos(15, 2)).is_err());
+    assert!(map.set_locked_at(10, 5, false, &mut entities));
+    assert!(map.getpath(Pos(2, 2), Pos(15, 2)).is_ok());
+
+    // A tile holds one building, the one there has to go first
+    let count = entities.getbuildings().len();
+    assert!(map.set_building_at(10, 4, Wall::new(0, 10, 4, 1.0, entities.getindex()), &mut entities).is_err());
+    assert_eq!(entities.getbuildings().len(), count);
+    map.clear_building_at(10, 4, &mut entities).unwrap();
+    assert!(map.set_building_at(10, 4, Wall::new(0, 10, 4, 1.0, entities.getindex()), &mut entities).is_ok());
+    // or is swapped out for the new one in one go
+    map.replace_building_at(10, 4, Door::new(0, 10, 4, 1.0, entities.getindex()), &mut entities).unwrap();
+    assert_eq!(entities.getbuildings().len(), count);
+    assert!(map.get_building(10, 4).unwrap().islockable());
+  }
+}
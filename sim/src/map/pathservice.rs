@@ -0,0 +1,167 @@
+use super::{Map, Pos, path_adjacent};
+use super::hpa::Hpa;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Sender, Receiver};
+use std::thread::{self, JoinHandle};
+
+/// What a worker needs to find a path, shared with the map as it was when the request was made.
+/// The map copies its costmap and Hpa before changing them while a search still holds them
+struct Snapshot {
+  version: u64,
+  width: i32,
+  height: i32,
+  costmap: Arc<Vec<usize>>,
+  hpa: Arc<Hpa>,
+}
+
+struct PathRequest {
+  ticket: u64,
+  actor: u64,
+  from: Pos,
+  to: Pos,
+  adjacent: bool,
+  snapshot: Snapshot,
+}
+
+pub struct PathResult {
+  pub ticket: u64,
+  pub actor: u64,
+  pub from: Pos,
+  pub to: Pos,
+  pub adjacent: bool, // Asked for a path to next to `to` rather than onto it
+  pub version: u64,   // Map version the path was found on
+  pub path: Option<Vec<Pos>>,
+}
+
+/// Finds paths on worker threads so long searches don't stall the frame.
+/// Requests are answered against a snapshot of the map taken when they were made. Workers finish
+/// them in whatever order they get to them, so results don't come back in ticket order
+pub struct PathService {
+  requests: Option<Sender<PathRequest>>,
+  results: Receiver<PathResult>,
+  workers: Vec<JoinHandle<()>>,
+  nextticket: u64,
+  outstanding: usize, // Requests sent that haven't been answered yet
+}
+
+impl PathService {
+  pub fn new(threads: usize) -> Self {
+    let (reqtx, reqrx) = channel::<PathRequest>();
+    let (restx, resrx) = channel::<PathResult>();
+    let reqrx = Arc::new(Mutex::new(reqrx));
+    let mut workers = Vec::new();
+    for _ in 0..threads.max(1) {
+      let reqrx = reqrx.clone();
+      let restx = restx.clone();
+      workers.push(thread::spawn(move || {
+        loop {
+          let r = match reqrx.lock().unwrap().recv() {
+            Ok(r) => r,
+            Err(_) => break, // Service dropped
+          };
+          let s = &r.snapshot;
+          let path = if r.adjacent {
+            path_adjacent(&s.costmap, s.width, s.height, &r.from, &r.to)
+          } else {
+            s.hpa.getpath(&r.from, &r.to, &s.costmap)
+          };
+          let res = PathResult { ticket: r.ticket, actor: r.actor, from: r.from, to: r.to, adjacent: r.adjacent, version: s.version, path };
+          if restx.send(res).is_err() { break; }
+        }
+      }));
+    }
+    PathService { requests: Some(reqtx), results: resrx, workers, nextticket: 0, outstanding: 0 }
+  }
+
+  /// Queue a path search for an actor. Returns the ticket its result will carry
+  pub fn request(&mut self, actor: u64, from: Pos, to: Pos, map: &Map) -> u64 {
+    self.send(actor, from, to, false, map)
+  }
+
+  /// Queue a search for a path to any tile next to `to`, like Map::getpath_adjacent
+  pub fn request_adjacent(&mut self, actor: u64, from: Pos, to: Pos, map: &Map) -> u64 {
+    self.send(actor, from, to, true, map)
+  }
+
+  fn send(&mut self, actor: u64, from: Pos, to: Pos, adjacent: bool, map: &Map) -> u64 {
+    let snapshot = Snapshot { version: map.version, width: map.width, height: map.height, costmap: map.costmap.clone(), hpa: map.hpa.clone() };
+    let ticket = self.nextticket;
+    self.nextticket = self.nextticket + 1;
+    if let Some(tx) = &self.requests {
+      if tx.send(PathRequest { ticket, actor, from, to, adjacent, snapshot }).is_ok() {
+        self.outstanding = self.outstanding + 1;
+      }
+    }
+    ticket
+  }
+
+  /// Results that have come in since the last call, in no particular order. Which ones are done
+  /// depends on how fast the workers are, so this is for callers that don't need repeatable runs
+  pub fn poll(&mut self) -> Vec<PathResult> {
+    let results: Vec<PathResult> = self.results.try_iter().collect();
+    self.outstanding = self.outstanding.saturating_sub(results.len());
+    results
+  }
+
+  /// Block until every request made so far is answered, and return the results in ticket order
+  pub fn wait(&mut self) -> Vec<PathResult> {
+    let mut results = Vec::new();
+    while self.outstanding > 0 {
+      match self.results.recv() {
+        Ok(r) => {
+          results.push(r);
+          self.outstanding = self.outstanding - 1;
+        }
+        Err(_) => { self.outstanding = 0; } // Workers are gone, nothing else is coming
+      }
+    }
+    results.sort_unstable_by_key(|r| r.ticket);
+    results
+  }
+}
+
+impl Drop for PathService {
+  fn drop(&mut self) {
+    self.requests = None; // Closing the channel lets the workers finish
+    for w in self.workers.drain(..) {
+      w.join().ok();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::test_map;
+  use super::super::super::entities::{Entities, Actor, Wall};
+
+  /// A path found on a map that has since changed is asked for again, which takes another round
+  fn wait(entities: &mut Entities, paths: &mut PathService, map: &Map) {
+    for _ in 0..3 {
+      entities.collect_paths(paths.wait(), paths, map);
+      if !entities.getactors()[0].ispending() { return; }
+    }
+    panic!("path never arrived");
+  }
+
+  #[test]
+  fn test_paths_arrive_later() {
+    let mut map = test_map(40, 40);
+    let mut paths = PathService::new(2);
+    let mut entities = Entities::new();
+    entities.add_actor(Actor::new(0, 1, 5, 1.0));
+
+    assert!(entities.get_actor_by_id(0).unwrap().setmovetarget(30, 5, &map, &mut paths));
+    assert!(entities.getactors()[0].ispending());
+    assert!(!entities.getactors()[0].ismoving());
+
+    // The map changes under the request: the straight path is now blocked, so it is asked for again
+    let mut other = Entities::new();
+    map.set_building_at(10, 5, Wall::new(0, 10, 5, 1.0, 99), &mut other).unwrap();
+    wait(&mut entities, &mut paths, &map);
+    let a = &entities.getactors()[0];
+    assert!(a.ismoving());
+    assert!(!a.getsteps().contains(&(10, 5)));
+    assert_eq!(a.getsteps().last(), Some(&(30, 5)));
+  }
+}
@@ -0,0 +1,113 @@
+use super::{Pos, getmapvecidx};
+
+const NO_REGION: u32 = 0; // Label of impassable tiles
+
+/// Connected areas of walkable tiles. Two tiles with the same label can reach each other.
+/// Diagonal steps need both orthogonal tiles open, so 4-way flood fills give the same areas as 8-way paths
+pub struct Regions {
+  width: i32,
+  height: i32,
+  labels: Vec<u32>,
+  next: u32,
+}
+
+impl Regions {
+  pub fn new(costmap: &[usize], width: i32, height: i32) -> Self {
+    let mut r = Regions { width, height, labels: vec![NO_REGION; (width * height) as usize], next: NO_REGION + 1 };
+    for y in 0..height {
+      for x in 0..width {
+        if r.label_at(x, y) == NO_REGION && Regions::open(costmap, x, y, width, height) {
+          r.fill(x, y, costmap);
+        }
+      }
+    }
+    r
+  }
+
+  fn open(costmap: &[usize], x: i32, y: i32, width: i32, height: i32) -> bool {
+    x >= 0 && x < width && y >= 0 && y < height && costmap[getmapvecidx(x, y, width)] < usize::MAX
+  }
+
+  pub fn label_at(&self, x: i32, y: i32) -> u32 {
+    if x < 0 || x >= self.width || y < 0 || y >= self.height { return NO_REGION; }
+    self.labels[getmapvecidx(x, y, self.width)]
+  }
+
+  /// True if a path between the two tiles exists. Constant time
+  pub fn is_reachable(&self, from: &Pos, to: &Pos) -> bool {
+    let a = self.label_at(from.0, from.1);
+    a != NO_REGION && a == self.label_at(to.0, to.1)
+  }
+
+  /// Give every open tile connected to (x, y) a new label
+  fn fill(&mut self, x: i32, y: i32, costmap: &[usize]) {
+    let label = self.next;
+    self.next = self.next + 1;
+    let (w, h) = (self.width, self.height);
+    let mut stack = vec![(x, y)];
+    self.labels[getmapvecidx(x, y, w)] = label;
+    while let Some((x, y)) = stack.pop() {
+      for (a, b) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)].iter().cloned() {
+        if !Regions::open(costmap, a, b, w, h) { continue; }
+        let idx = getmapvecidx(a, b, w);
+        if self.labels[idx] == label { continue; }
+        self.labels[idx] = label;
+        stack.push((a, b));
+      }
+    }
+  }
+
+  /// The costmap changed at (x, y). Opening a tile can join regions, closing one can split them,
+  /// so the areas touching it are flooded again
+  pub fn update(&mut self, x: i32, y: i32, costmap: &[usize]) {
+    if x < 0 || x >= self.width || y < 0 || y >= self.height { return; }
+    let (w, h) = (self.width, self.height);
+    if Regions::open(costmap, x, y, w, h) {
+      let around = [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)];
+      let mut labels: Vec<u32> = around.iter().map(|(a, b)| self.label_at(*a, *b)).filter(|l| *l != NO_REGION).collect();
+      labels.dedup();
+      if self.label_at(x, y) != NO_REGION && labels.iter().all(|l| *l == self.label_at(x, y)) {
+        return; // Only the cost changed
+      }
+      self.fill(x, y, costmap);
+    } else {
+      self.labels[getmapvecidx(x, y, w)] = NO_REGION;
+      let mut done: Vec<u32> = Vec::new();
+      for (a, b) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)].iter().cloned() {
+        let l = self.label_at(a, b);
+        if l == NO_REGION || done.contains(&l) { continue; }
+        self.fill(a, b, costmap);
+        done.push(self.label_at(a, b));
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::test_map;
+  use super::super::super::entities::{Entities, Wall};
+
+  #[test]
+  fn test_rooms_split_and_join() {
+    let mut map = test_map(12, 12);
+    let mut entities = Entities::new();
+    let (inside, outside) = (Pos(5, 5), Pos(0, 0));
+    // Ring of walls from (3, 3) to (7, 7)
+    for i in 3..8 {
+      for (x, y) in [(i, 3), (i, 7), (3, i), (7, i)].iter().cloned() {
+        if !map.get_building_at(x, y) {
+          map.set_building_at(x, y, Wall::new(0, x, y, 1.0, entities.getindex()), &mut entities).unwrap();
+        }
+      }
+    }
+    assert!(!map.is_reachable(&outside, &inside));
+    assert!(map.is_reachable(&Pos(4, 4), &inside));
+    assert!(!map.is_reachable(&outside, &Pos(3, 3))); // Walls are in no region
+
+    map.clear_building_at(7, 5, &mut entities).unwrap();
+    assert!(map.is_reachable(&outside, &inside));
+    assert!(map.getpath(outside, inside).is_ok());
+  }
+}
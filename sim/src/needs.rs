@@ -0,0 +1,334 @@
+use crate::entities::{Entities, Entity, Actor};
+use crate::items::ItemKind;
+use crate::jobs::Jobs;
+use crate::map::{Map, Pos};
+use crate::tiles::TileRegistry;
+
+pub const FULL: f32 = 0.95; // An actor stops seeing to a need once it's up to this
+
+// ms of simulation time for a full need to run out, and to fill an empty one at a bed or table
+const HUNGER_DECAY: f32 = 360000.0;
+const REST_DECAY: f32 = 480000.0;
+const JOY_DECAY: f32 = 300000.0;
+const EAT_TIME: f32 = 20000.0;   // A meal of one food, or foraging on the most fertile ground. Poorer ground is slower
+const SLEEP_TIME: f32 = 30000.0;
+const RELAX_TIME: f32 = 15000.0;
+const MAKESHIFT: f32 = 0.5;      // Sleeping on the ground or relaxing without a table is this much as good
+
+const FORAGE_FERTILITY: f32 = 0.5; // Poorest ground that has anything to eat
+const FORAGE_STEPS: usize = 30;    // How far an actor looks for something to eat
+
+// How long a need can stay empty before things get worse
+const COLLAPSE_MS: u32 = 30000;  // No rest: falls asleep where it stands
+const STARVE_MS: u32 = 60000;    // No food: starts losing health
+const BREAK_MS: u32 = 60000;     // No joy: refuses to work until it has had some
+const STARVE_DAMAGE: f32 = 1.0 / 60000.0; // Health per ms while starving
+const HEAL_RATE: f32 = 1.0 / 120000.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Need {
+  Hunger,
+  Rest,
+  Joy,
+}
+
+pub const NEEDS: [Need; 3] = [Need::Hunger, Need::Rest, Need::Joy];
+
+impl Need {
+  fn index(&self) -> usize {
+    match self {
+      Need::Hunger => 0,
+      Need::Rest => 1,
+      Need::Joy => 2,
+    }
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Need::Hunger => "hunger",
+      Need::Rest => "rest",
+      Need::Joy => "joy",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Need> {
+    NEEDS.iter().cloned().find(|n| n.name() == name)
+  }
+
+  /// What an actor is doing while seeing to this need
+  pub fn verb(&self) -> &'static str {
+    match self {
+      Need::Hunger => "eating",
+      Need::Rest => "sleeping",
+      Need::Joy => "relaxing",
+    }
+  }
+
+  fn decay(&self) -> f32 {
+    match self {
+      Need::Hunger => 1.0 / HUNGER_DECAY,
+      Need::Rest => 1.0 / REST_DECAY,
+      Need::Joy => 1.0 / JOY_DECAY,
+    }
+  }
+
+  fn refill(&self) -> f32 {
+    match self {
+      Need::Hunger => 1.0 / EAT_TIME,
+      Need::Rest => 1.0 / SLEEP_TIME,
+      Need::Joy => 1.0 / RELAX_TIME,
+    }
+  }
+}
+
+/// How satisfied an actor is, 0...1 per need, and how long each need has been empty
+#[derive(Clone, Debug)]
+pub struct Needs {
+  levels: [f32; 3],
+  empty: [u32; 3],
+}
+
+impl Default for Needs {
+  fn default() -> Self { Needs::new() }
+}
+
+impl Needs {
+  pub fn new() -> Self {
+    Needs { levels: [1.0; 3], empty: [0; 3] }
+  }
+
+  pub fn get(&self, n: Need) -> f32 { self.levels[n.index()] }
+
+  pub fn set(&mut self, n: Need, v: f32) {
+    self.levels[n.index()] = v.clamp(0.0, 1.0);
+    if self.levels[n.index()] > 0.0 { self.empty[n.index()] = 0; }
+  }
+
+  pub fn add(&mut self, n: Need, v: f32) { let l = self.get(n); self.set(n, l + v); }
+
+  /// ms the need has been at zero
+  pub fn emptyfor(&self, n: Need) -> u32 { self.empty[n.index()] }
+
+  /// Speed of walking and working. Anything at zero slows an actor down
+  pub fn workrate(&self) -> f32 {
+    if self.levels.iter().any(|l| *l <= 0.0) { 0.5 } else { 1.0 }
+  }
+
+  pub fn isstarving(&self) -> bool { self.emptyfor(Need::Hunger) > STARVE_MS }
+
+  /// Gone too long without joy, won't take jobs until it has had some
+  pub fn isbroken(&self) -> bool { self.emptyfor(Need::Joy) > BREAK_MS }
+
+  /// Gone so long without rest it falls asleep wherever it is
+  pub fn iscollapsing(&self) -> bool { self.emptyfor(Need::Rest) > COLLAPSE_MS }
+
+  fn count_empty(&mut self, deltaT: u32) {
+    for i in 0..self.levels.len() {
+      if self.levels[i] <= 0.0 { self.empty[i] = self.empty[i] + deltaT; }
+    }
+  }
+}
+
+/// Tile where an actor could see to a need. Hungry actors go for the nearest food and forage if
+/// there's none, without a free bed or table they make do where they stand. None if there is
+/// nowhere, e.g. no food or fertile ground in reach. Only checks the tile can be reached, the path
+/// there is left to the path service
+pub fn find_spot(a: &Actor, need: Need, entities: &Entities, taken: &[(i32, i32)], tiles: &TileRegistry, map: &Map) -> Option<(i32, i32)> {
+  let here = Pos::from(a.getoccupiedtile());
+  match need {
+    Need::Hunger => {
+      let food = map.getitems().iter()
+        .filter(|(t, s)| s.kind == ItemKind::Food && !taken.contains(t) && map.is_reachable(&here, &Pos(t.0, t.1)))
+        .map(|(t, _)| *t)
+        .min_by_key(|t| (here.distance(&Pos(t.0, t.1)), *t));
+      if food.is_some() { return food; }
+      let fertile = |p: &Pos| -> bool {
+        map.get_tile_at(p.0, p.1).ok().and_then(|(t, _)| tiles.get(t.id)).is_some_and(|d| d.fertility >= FORAGE_FERTILITY)
+          && !taken.contains(&(p.0, p.1))
+      };
+      if fertile(&here) { return Some((here.0, here.1)); }
+      // A short search outwards, foraging is about what's close by
+      map.path_aside(&here, |_| false, |p| !fertile(p), FORAGE_STEPS).and_then(|p| p.last().map(|t| (t.0, t.1)))
+    }
+    Need::Rest | Need::Joy => {
+      let mut spots: Vec<(i32, i32)> = entities.getbuildings().values()
+        .filter(|b| b.getservice() == Some(need))
+        .map(|b| b.getoccupiedtile())
+        .filter(|t| !taken.contains(t) && map.is_reachable(&here, &Pos(t.0, t.1)))
+        .collect();
+      spots.sort_by_key(|t| (here.distance(&Pos(t.0, t.1)), *t));
+      Some(spots.first().cloned().unwrap_or((here.0, here.1))) // Or sleep on the ground, or just sit down for a bit
+    }
+  }
+}
+
+/// Needs run down over time, and fill up while an actor sees to them (the Brain decides when).
+/// A meal eaten on a food stack uses up one food once the actor is full.
+/// Needs left empty get worse: slower work, collapsing, starving, refusing to work
+pub fn update(deltaT: u32, entities: &mut Entities, jobs: &mut Jobs, tiles: &TileRegistry, map: &mut Map) {
+  let dt = deltaT as f32;
+  let mut dead = Vec::new();
+  for i in 0..entities.getactors().len() {
+    let a = &entities.getactors()[i];
+    let here = a.getoccupiedtile();
+    let free = !a.ismoving() && !a.ispending();
+    let satisfying = match a.getactivity() {
+      Some((n, t)) if free && t == here => Some(n),
+      _ => None,
+    };
+    let meal = map.get_items_at(here.0, here.1).is_some_and(|s| s.kind == ItemKind::Food);
+    // The real thing or making do, e.g. foraging on poor ground is slow
+    let quality = match satisfying {
+      Some(Need::Hunger) if meal => 1.0,
+      Some(Need::Hunger) => map.get_tile_at(here.0, here.1).ok().and_then(|(t, _)| tiles.get(t.id)).map_or(0.0, |d| d.fertility),
+      Some(n) => if entities.getbuildings().values().any(|b| b.getservice() == Some(n) && b.getoccupiedtile() == here) { 1.0 } else { MAKESHIFT },
+      None => 0.0,
+    };
+
+    let a = &mut entities.getactors_mut()[i];
+    for n in NEEDS.iter().cloned() {
+      if satisfying == Some(n) {
+        a.needs.add(n, n.refill() * quality * dt);
+      } else {
+        a.needs.add(n, -n.decay() * dt);
+      }
+    }
+    a.needs.count_empty(deltaT);
+    if a.needs.isstarving() {
+      a.health = a.health - (STARVE_DAMAGE * dt);
+      if a.health <= 0.0 { dead.push(a.getentityid()); }
+    } else if a.needs.get(Need::Hunger) > 0.0 {
+      a.health = (a.health + (HEAL_RATE * dt)).min(1.0);
+    }
+
+    match satisfying {
+      Some(n) if a.needs.get(n) >= FULL => {
+        if n == Need::Hunger && meal { map.take_items_at(here.0, here.1, ItemKind::Food, 1); }
+        a.setactivity(None);
+      }
+      Some(_) => {}
+      None if free => { a.setactivity(None); } // Got somewhere else, or the way was blocked
+      None => {}
+    }
+    if a.needs.iscollapsing() && satisfying != Some(Need::Rest) {
+      jobs.release_actor(a.getentityid());
+      a.stop();
+      a.setactivity(Some((Need::Rest, here)));
+    }
+  }
+  for id in dead {
+    jobs.release_actor(id);
+    if let Some(a) = entities.get_actor_by_id(id) {
+      let (x, y) = a.getoccupiedtile();
+      if let Some(s) = a.carrying.take() { map.drop_items(x, y, s.kind, s.count); } // Left where it fell
+    }
+    entities.remove_actor(id);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::map::test_map;
+  use crate::entities::{Furniture, BED_SPRITE, WALL_MATERIALS};
+  use crate::items::Stack;
+  use crate::jobs::JobKind;
+  use crate::ai::{Brain, Colony};
+  use crate::map::pathservice::PathService;
+
+  #[test]
+  fn test_needs_interrupt_work() {
+    let tiles = TileRegistry::standard();
+    let mut map = test_map(20, 20); // All grass, so there is food everywhere
+    let mut entities = Entities::new();
+    let mut jobs = Jobs::new();
+    entities.add_actor(Actor::new(0, 2, 2, 1.0));
+    let bed = Furniture::new(BED_SPRITE, 12, 2, 1.0, entities.getindex(), Need::Rest);
+    map.set_building_at(12, 2, bed, &mut entities).unwrap();
+    jobs.add(JobKind::BuildWall, 6, 15);
+    map.add_items_at(6, 15, ItemKind::Wood, WALL_MATERIALS);
+    let mut brain = Brain::standard();
+    let mut paths = PathService::new(1);
+
+    for _ in 0..40 {
+      entities.collect_paths(paths.wait(), &mut paths, &map);
+      entities.update(16, 64.0, &map);
+      jobs.update(16, &mut entities, &mut map, &mut paths).unwrap();
+      update(16, &mut entities, &mut jobs, &tiles, &mut map);
+      brain.update(16, &mut Colony { entities: &mut entities, jobs: &mut jobs, tiles: &tiles, map: &map, paths: &mut paths, threats: &[] });
+    }
+    assert!(jobs.job_for(0).is_some());
+
+    // Tired: off to bed, and the job goes back in the queue
+    entities.get_actor_by_id(0).unwrap().needs.set(Need::Rest, 0.1);
+    for _ in 0..500 {
+      entities.collect_paths(paths.wait(), &mut paths, &map);
+      entities.update(16, 64.0, &map);
+      jobs.update(16, &mut entities, &mut map, &mut paths).unwrap();
+      update(16, &mut entities, &mut jobs, &tiles, &mut map);
+      brain.update(16, &mut Colony { entities: &mut entities, jobs: &mut jobs, tiles: &tiles, map: &map, paths: &mut paths, threats: &[] });
+    }
+    assert!(jobs.job_for(0).is_none());
+    let a = &entities.getactors()[0];
+    assert_eq!(a.getactivity(), Some((Need::Rest, (12, 2))));
+    assert_eq!(a.getoccupiedtile(), (12, 2));
+    assert!(a.needs.get(Need::Rest) > 0.1);
+
+    // Hungry on fertile ground: eats where it stands
+    let mut e = Entities::new();
+    e.add_actor(Actor::new(0, 5, 5, 1.0));
+    e.get_actor_by_id(0).unwrap().needs.set(Need::Hunger, 0.2);
+    for _ in 0..40 {
+      brain.update(16, &mut Colony { entities: &mut e, jobs: &mut jobs, tiles: &tiles, map: &map, paths: &mut paths, threats: &[] });
+    }
+    assert_eq!(e.getactors()[0].getactivity(), Some((Need::Hunger, (5, 5))));
+  }
+
+  #[test]
+  fn test_eats_food_before_foraging() {
+    let tiles = TileRegistry::standard();
+    let mut map = test_map(20, 20);
+    let mut entities = Entities::new();
+    let mut jobs = Jobs::new();
+    let mut brain = Brain::standard();
+    let mut paths = PathService::new(1);
+    entities.add_actor(Actor::new(0, 2, 2, 1.0));
+    entities.get_actor_by_id(0).unwrap().needs.set(Need::Hunger, 0.2);
+    map.add_items_at(9, 6, ItemKind::Food, 5);
+
+    for _ in 0..((EAT_TIME as u32 / 16) + 200) {
+      entities.collect_paths(paths.wait(), &mut paths, &map);
+      entities.update(16, 64.0, &map);
+      update(16, &mut entities, &mut jobs, &tiles, &mut map);
+      brain.update(16, &mut Colony { entities: &mut entities, jobs: &mut jobs, tiles: &tiles, map: &map, paths: &mut paths, threats: &[] });
+      if entities.getactors()[0].needs.get(Need::Hunger) >= FULL { break; }
+    }
+    assert_eq!(entities.getactors()[0].getoccupiedtile(), (9, 6));
+    assert!(entities.getactors()[0].needs.get(Need::Hunger) >= FULL);
+    assert_eq!(map.get_items_at(9, 6).map(|s| s.count), Some(4));
+  }
+
+  #[test]
+  fn test_starving() {
+    let tiles = TileRegistry::new(); // No terrain at all, so nothing to eat anywhere
+    let mut map = test_map(10, 10);
+    let mut entities = Entities::new();
+    let mut jobs = Jobs::new();
+    entities.add_actor(Actor::new(0, 2, 2, 1.0));
+    entities.get_actor_by_id(0).unwrap().needs.set(Need::Hunger, 0.0);
+
+    update(16, &mut entities, &mut jobs, &tiles, &mut map);
+    assert_eq!(entities.getactors()[0].needs.workrate(), 0.5);
+    for _ in 0..((STARVE_MS / 16) + 10) {
+      update(16, &mut entities, &mut jobs, &tiles, &mut map);
+    }
+    assert!(entities.getactors()[0].needs.isstarving());
+    assert!(entities.getactors()[0].health < 1.0);
+    entities.get_actor_by_id(0).unwrap().carrying = Some(Stack::new(ItemKind::Wood, 3));
+    for _ in 0..(61000 / 16) {
+      update(16, &mut entities, &mut jobs, &tiles, &mut map);
+    }
+    assert!(entities.getactors().is_empty());
+    assert_eq!(map.get_items_at(2, 2).map(|s| s.count), Some(3));
+  }
+}
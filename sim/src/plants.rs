@@ -0,0 +1,210 @@
+use crate::map::Map;
+use crate::rng::Rng;
+use crate::tiles::TileRegistry;
+
+pub const STAGES: u8 = 3;            // Seedling, young, grown
+const GROW_INTERVAL: u32 = 5000;     // ms between growth steps, there are a lot of plants
+const TREE_STAGE_MS: u32 = 120_000;  // ms a stage takes on the most fertile ground
+const SHRUB_STAGE_MS: u32 = 45_000;
+const SPREAD_CHANCE: f32 = 0.005;    // Per grown plant per step, before the ground's fertility
+const CROWDED: usize = 3;            // Plants around a tile that stop anything new taking root
+const RNG_STREAM: u32 = 0x2545_f491; // Mixed into the world seed, so growth doesn't follow other random rolls
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PlantKind {
+  Tree,
+  Shrub,
+}
+
+pub const PLANTS: [PlantKind; 2] = [PlantKind::Tree, PlantKind::Shrub];
+
+impl PlantKind {
+  pub fn name(&self) -> &'static str {
+    match self {
+      PlantKind::Tree => "tree",
+      PlantKind::Shrub => "shrub",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<PlantKind> {
+    PLANTS.iter().cloned().find(|k| k.name() == name)
+  }
+
+  fn stagetime(&self) -> u32 {
+    match self {
+      PlantKind::Tree => TREE_STAGE_MS,
+      PlantKind::Shrub => SHRUB_STAGE_MS,
+    }
+  }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Plant {
+  pub kind: PlantKind,
+  pub stage: u8,    // 0...STAGES-1
+  pub growth: f32,  // ms grown towards the next stage, already scaled by fertility
+}
+
+impl Plant {
+  pub fn new(kind: PlantKind, stage: u8) -> Self {
+    Plant { kind, stage: stage.min(STAGES - 1), growth: 0.0 }
+  }
+
+  pub fn isgrown(&self) -> bool { self.stage == STAGES - 1 }
+
+  /// Name of the sprite in import.xml for a kind at a stage, "tree0" for a tree seedling
+  pub fn spritename(kind: PlantKind, stage: u8) -> String {
+    format!("{}{}", kind.name(), stage)
+  }
+
+  /// Path cost of the tile it grows on, 0 where it doesn't get in the way
+  pub fn movecost(&self) -> usize {
+    match (self.kind, self.stage) {
+      (PlantKind::Tree, 2) => 4,
+      (PlantKind::Tree, 1) => 2,
+      (PlantKind::Shrub, 2) => 2,
+      _ => 0,
+    }
+  }
+
+  /// Wood left behind when it's cut down
+  pub fn wood(&self) -> u32 {
+    match (self.kind, self.stage) {
+      (PlantKind::Tree, 2) => 20,
+      (PlantKind::Tree, 1) => 6,
+      (PlantKind::Shrub, 2) => 2,
+      _ => 0,
+    }
+  }
+}
+
+/// 0...1 from a tile and a seed, the same every time. For scattering things over a map
+pub fn scatter(seed: u32, x: i32, y: i32) -> f32 {
+  let mut h = seed ^ (x as u32).wrapping_mul(0x85eb_ca6b) ^ (y as u32).wrapping_mul(0xc2b2_ae35);
+  h ^= h >> 16;
+  h = h.wrapping_mul(0x7feb_352d);
+  h ^= h >> 15;
+  h = h.wrapping_mul(0x846c_a68b);
+  h ^= h >> 16;
+  (h % 10_000) as f32 / 10_000.0
+}
+
+/// Grows the map's plants a stage at a time, and lets grown ones seed the ground around them.
+/// Both go faster the more fertile the ground, and nothing grows on barren tiles
+pub struct Growth {
+  timer: u32,
+  rng: Rng,
+}
+
+impl Default for Growth {
+  fn default() -> Self { Growth::new(0) }
+}
+
+impl Growth {
+  pub fn new(seed: u32) -> Self {
+    Growth { timer: 0, rng: Rng::new(seed ^ RNG_STREAM) }
+  }
+
+  /// One line of a save file: time since the last step and the random state
+  pub fn serialize(&self) -> String {
+    format!("growth {} {}", self.timer, self.rng.getstate())
+  }
+
+  pub fn deserialize(tokens: &[&str]) -> Option<Growth> {
+    let timer = tokens.first()?.parse::<u32>().ok()?;
+    let rng = Rng::new(tokens.get(1)?.parse::<u32>().ok()?);
+    Some(Growth { timer, rng })
+  }
+
+  pub fn update(&mut self, deltaT: u32, map: &mut Map, tiles: &TileRegistry) {
+    self.timer = self.timer + deltaT;
+    if self.timer < GROW_INTERVAL { return; }
+    let elapsed = self.timer as f32;
+    self.timer = 0;
+
+    let mut spots: Vec<((i32, i32), Plant)> = map.getplants().iter().map(|(t, p)| (*t, *p)).collect();
+    spots.sort_unstable_by_key(|(t, _)| *t); // HashMap order isn't repeatable, and the rolls below depend on it
+    let mut grown = Vec::new();
+    let mut seeds = Vec::new();
+    for ((x, y), p) in spots {
+      if p.isgrown() {
+        if self.rng.chance() < SPREAD_CHANCE {
+          let (dx, dy) = (self.rng.range(2), self.rng.range(2));
+          seeds.push(((x + dx, y + dy), p.kind));
+        }
+        continue;
+      }
+      let growth = p.growth + (elapsed * fertility_at(map, tiles, x, y));
+      if growth >= p.kind.stagetime() as f32 {
+        grown.push(((x, y), Plant::new(p.kind, p.stage + 1)));
+      } else if let Some(p) = map.plants.get_mut(&(x, y)) {
+        p.growth = growth; // Doesn't change the cost, so no need to go through set_plant_at
+      }
+    }
+    for ((x, y), p) in grown {
+      map.set_plant_at(x, y, p);
+    }
+    for ((x, y), kind) in seeds {
+      if !map.ispassable(x, y) || map.get_plant_at(x, y).is_some() { continue; }
+      if map.get_building(x, y).is_some() || map.get_items_at(x, y).is_some() { continue; }
+      let around = (-1..=1).flat_map(|dx| (-1..=1).map(move |dy| (x + dx, y + dy)))
+        .filter(|(a, b)| map.get_plant_at(*a, *b).is_some())
+        .count();
+      if around > CROWDED { continue; }
+      if self.rng.chance() < fertility_at(map, tiles, x, y) {
+        map.set_plant_at(x, y, Plant::new(kind, 0));
+      }
+    }
+  }
+}
+
+fn fertility_at(map: &Map, tiles: &TileRegistry, x: i32, y: i32) -> f32 {
+  match map.get_tile_at(x, y) {
+    Ok((t, _)) => tiles.get(t.id).map_or(0.0, |d| d.fertility),
+    Err(_) => 0.0,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::map::test_map;
+
+  #[test]
+  fn test_growth_and_cost() {
+    let tiles = TileRegistry::standard();
+    let mut map = test_map(10, 10); // grass0, fertility 0.5
+    map.set_plant_at(4, 4, Plant::new(PlantKind::Tree, 0));
+    assert_eq!(map.costmap[map.getmapvecidx(4, 4)], 1); // Seedlings don't get in the way
+
+    let mut growth = Growth::new(map.seed);
+    assert_ne!(growth.serialize(), Growth::new(map.seed ^ 1).serialize());
+    let stage = (TREE_STAGE_MS as f32 / 0.5) as u32;
+    for _ in 0..(stage / GROW_INTERVAL) {
+      growth.update(GROW_INTERVAL, &mut map, &tiles);
+    }
+    assert_eq!(map.get_plant_at(4, 4).map(|p| p.stage), Some(1));
+    assert_eq!(map.costmap[map.getmapvecidx(4, 4)], 2);
+    for _ in 0..(stage / GROW_INTERVAL) {
+      growth.update(GROW_INTERVAL, &mut map, &tiles);
+    }
+    assert!(map.get_plant_at(4, 4).unwrap().isgrown());
+    assert_eq!(map.costmap[map.getmapvecidx(4, 4)], 4);
+    assert!(map.ispassable(4, 4));
+
+    // Left long enough it seeds the ground around it, but never barren ground
+    let mut barren = TileRegistry::standard();
+    barren.add("grass0", 1, true, 0.0);
+    for _ in 0..2000 {
+      growth.update(GROW_INTERVAL, &mut map, &barren);
+    }
+    assert_eq!(map.getplants().len(), 1);
+    for _ in 0..2000 {
+      growth.update(GROW_INTERVAL, &mut map, &tiles);
+    }
+    assert!(map.getplants().len() > 1);
+
+    assert_eq!(map.take_plant_at(4, 4).map(|p| p.wood()), Some(20));
+    assert_eq!(map.costmap[map.getmapvecidx(4, 4)], 1);
+  }
+}
@@ -0,0 +1,53 @@
+/// Small seeded xorshift generator. Everything random in the simulation comes from one of these,
+/// so a run from the same seeds comes out the same every time
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rng {
+  state: u32,
+}
+
+impl Rng {
+  /// Any seed will do, a zero state would only ever give zeroes so it's nudged off it
+  pub fn new(seed: u32) -> Self {
+    Rng { state: seed.max(1) }
+  }
+
+  /// Where the generator is, enough to carry on from the same spot with Rng::new
+  pub fn getstate(&self) -> u32 { self.state }
+
+  pub fn next_u32(&mut self) -> u32 {
+    self.state ^= self.state << 13;
+    self.state ^= self.state >> 17;
+    self.state ^= self.state << 5;
+    self.state
+  }
+
+  /// 0...1
+  pub fn chance(&mut self) -> f32 {
+    (self.next_u32() % 10_000) as f32 / 10_000.0
+  }
+
+  /// -r...r
+  pub fn range(&mut self, r: i32) -> i32 {
+    (self.next_u32() % (((r * 2) + 1) as u32)) as i32 - r
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_repeatable() {
+    let mut a = Rng::new(42);
+    let mut b = Rng::new(Rng::new(42).getstate());
+    for _ in 0..100 {
+      let r = a.range(3);
+      assert_eq!(r, b.range(3));
+      assert!((-3..=3).contains(&r));
+      let c = a.chance();
+      assert_eq!(c, b.chance());
+      assert!((0.0..1.0).contains(&c));
+    }
+    assert_ne!(Rng::new(0).next_u32(), 0);
+  }
+}
@@ -0,0 +1,471 @@
+use crate::map::{Map, getmapvecidx};
+use crate::entities::{Entities, Entity, Actor, Wall, Door, Furniture, Blueprint, Buildable, Tile};
+use crate::needs::Need;
+use crate::jobs::{Jobs, JobKind};
+use crate::items::{ItemKind, Stockpiles};
+use crate::plants::{Plant, PlantKind, Growth, STAGES};
+use crate::ai::Brain;
+use crate::tiles::TileRegistry;
+use crate::world::World;
+use crate::error::{SimError, SimResult};
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+const SAVE_MAGIC: &str = "RUSTWORLD";
+const SAVE_VERSION: u32 = 11; // Bump whenever the layout below changes
+pub const QUICKSAVE_PATH: &str = "quicksave.sav";
+
+/// Everything read back from a save file
+pub struct SaveGame {
+  pub map: Map,
+  pub entities: Entities,
+  pub jobs: Jobs,
+  pub stockpiles: Stockpiles,
+  pub brain: Brain,   // Standard behaviours, carrying on where the saved ones were
+  pub growth: Growth,
+  pub orders: Vec<(u64, (i32, i32))>, // Actors that were waiting on a path, and where to
+  pub camera: (f32, f32),
+  pub zoomstep: i32,
+}
+
+/// Save layout, one record per line:
+///   RUSTWORLD <version>
+///   map <seed> <width> <height>
+///   <height> lines of tiles, each "id:alt:movecost:edges"
+///   <height> lines of costmap values
+///   entityindex <n>
+///   wall ... / door ... / furniture ... / blueprint ... / actor ...   (one line per building or actor,
+///   actors keep their needs but not what they were doing about them, they decide again on load)
+///   order <actor entity id> <x> <y>   (a path being searched for, asked for again on load)
+///   job <kind> <x> <y> <progress>   (claims are dropped, actors pick jobs up again)
+///   haul <x> <y> <kind> <count> <to x> <to y>   (loads being carried are put down where their
+///   carriers stand, and the haul picks them up from there)
+///   items <x> <y> <kind> <count>
+///   plant <x> <y> <kind> <stage> <growth>
+///   stockpile <id> <kind,kind,...|-> <x,y> <x,y> ...
+///   growth <timer> <random state>
+///   brain <timer> <random state> <id:x,y:ms> ...   (wandering actors and where they wander)
+///   camera <x> <y> <zoomstep>
+///   end
+pub fn save_game<W: Write>(out: &mut W, world: &World, camera: (f32, f32), zoomstep: i32) -> SimResult<()> {
+  let (map, entities, jobs) = (&world.map, &world.entities, &world.jobs);
+  writeln!(out, "{} {}", SAVE_MAGIC, SAVE_VERSION)?;
+  writeln!(out, "map {} {} {}", map.seed, map.width, map.height)?;
+
+  for y in 0..map.height {
+    let row: Vec<String> = (0..map.width).map(|x| {
+      let (t, alt) = &map.tilemap[map.getmapvecidx(x, y)];
+      format!("{}:{}:{}:{}", t.id, alt, t.getmovecost(), t.getedges())
+    }).collect();
+    writeln!(out, "{}", row.join(" "))?;
+  }
+  for y in 0..map.height {
+    let row: Vec<String> = (0..map.width).map(|x| map.costmap[map.getmapvecidx(x, y)].to_string()).collect();
+    writeln!(out, "{}", row.join(" "))?;
+  }
+
+  writeln!(out, "entityindex {}", entities.getindex())?;
+  let mut ids: Vec<&u64> = entities.getbuildings().keys().collect();
+  ids.sort();
+  for id in ids {
+    writeln!(out, "{}", entities.getbuildings()[id].serialize())?;
+  }
+  for a in entities.getactors().iter() {
+    writeln!(out, "{}", a.serialize())?;
+  }
+  for a in entities.getactors().iter() {
+    if let Some((x, y)) = a.getorder() { writeln!(out, "order {} {} {}", a.getentityid(), x, y)?; }
+  }
+  for j in jobs.getjobs().iter().filter(|j| j.haul.is_none()) {
+    writeln!(out, "job {} {} {} {}", j.kind.name(), j.target.0, j.target.1, j.progress)?;
+  }
+  for j in jobs.getjobs().iter() {
+    let h = match &j.haul { Some(h) => h, None => continue };
+    let carrier = j.worker.and_then(|w| entities.getactors().iter().find(|a| a.getentityid() == w));
+    let (x, y) = match carrier {
+      Some(a) if h.carried => a.getoccupiedtile(),
+      _ => j.target,
+    };
+    writeln!(out, "haul {} {} {} {} {} {}", x, y, h.kind.name(), h.count, h.to.0, h.to.1)?;
+  }
+  let mut items: Vec<_> = map.getitems().iter().map(|(t, s)| (*t, *s)).collect();
+  items.sort_by_key(|(t, _)| *t);
+  let carried = entities.getactors().iter().filter_map(|a| a.carrying.map(|s| (a.getoccupiedtile(), s)));
+  for ((x, y), s) in items.into_iter().chain(carried) {
+    writeln!(out, "items {} {} {} {}", x, y, s.kind.name(), s.count)?;
+  }
+  let mut plants: Vec<_> = map.getplants().iter().collect();
+  plants.sort_by_key(|(t, _)| **t);
+  for ((x, y), p) in plants {
+    writeln!(out, "plant {} {} {} {} {}", x, y, p.kind.name(), p.stage, p.growth)?;
+  }
+  for z in world.stockpiles.getzones().iter() {
+    let kinds: Vec<&str> = z.accepts.iter().map(|k| k.name()).collect();
+    let tiles: Vec<String> = z.tiles.iter().map(|(x, y)| format!("{},{}", x, y)).collect();
+    let kinds = if kinds.is_empty() { "-".to_string() } else { kinds.join(",") };
+    writeln!(out, "stockpile {} {} {}", z.id, kinds, tiles.join(" "))?;
+  }
+  writeln!(out, "{}", world.growth.serialize())?;
+  writeln!(out, "{}", world.brain.serialize())?;
+
+  writeln!(out, "camera {} {} {}", camera.0, camera.1, zoomstep)?;
+  writeln!(out, "end")?;
+  Ok(())
+}
+
+/// Reads a save back, every tile id has to be one the registry knows
+pub fn load_game<R: BufRead>(input: R, tiles: &TileRegistry) -> SimResult<SaveGame> {
+  let mut lines = input.lines();
+  let mut next = || -> SimResult<String> {
+    match lines.next() {
+      Some(l) => Ok(l?),
+      None => Err(bad_save("unexpected end of file")),
+    }
+  };
+
+  let header = next()?;
+  let header: Vec<&str> = header.split_whitespace().collect();
+  if header.len() != 2 || header[0] != SAVE_MAGIC {
+    return Err(bad_save("not a Rustworld save"));
+  }
+  let version: u32 = parse(header.get(1), "version")?;
+  if version != SAVE_VERSION {
+    return Err(bad_save(&format!("save version {} is not supported (expected {})", version, SAVE_VERSION)));
+  }
+
+  let line = next()?;
+  let t: Vec<&str> = line.split_whitespace().collect();
+  if t.first() != Some(&"map") { return Err(bad_save("missing map header")); }
+  let seed: u32 = parse(t.get(1), "seed")?;
+  let width: i32 = parse(t.get(2), "width")?;
+  let height: i32 = parse(t.get(3), "height")?;
+  if width <= 0 || height <= 0 { return Err(bad_save("bad map size")); }
+
+  let mut tilemap = Vec::with_capacity((width * height) as usize);
+  for y in 0..height {
+    let line = next()?;
+    let row: Vec<&str> = line.split_whitespace().collect();
+    if row.len() != width as usize { return Err(bad_save("short tile row")); }
+    for (x, cell) in row.iter().enumerate() {
+      let mut c = cell.split(':');
+      let id: u32 = parse(c.next(), "tile id")?;
+      if tiles.get(id).is_none() { return Err(bad_save(&format!("unknown tile id {} at {},{}", id, x, y))); }
+      let alt: u32 = parse(c.next(), "tile alt")?;
+      let cost: usize = parse(c.next(), "tile cost")?;
+      let edges: u8 = parse(c.next(), "tile edges")?;
+      let mut tile = Tile::new(id, x as i32, y, 1.0);
+      tile.setalternate(&alt);
+      tile.setmovecost(cost);
+      tile.setedges(edges);
+      tilemap.push((tile, alt));
+    }
+  }
+
+  let mut costmap = Vec::with_capacity((width * height) as usize);
+  for _ in 0..height {
+    let line = next()?;
+    let row: Vec<&str> = line.split_whitespace().collect();
+    if row.len() != width as usize { return Err(bad_save("short cost row")); }
+    for cell in row {
+      let cost: usize = parse(Some(cell), "cost")?;
+      costmap.push(cost);
+    }
+  }
+
+  let mut build_layer = Vec::with_capacity((width * height) as usize);
+  build_layer.resize_with((width * height) as usize, || None);
+  let mut map = Map::from_layers(tilemap, build_layer, costmap, seed, width, height);
+  let mut entities = Entities::new();
+  let mut jobs = Jobs::new();
+  let mut stockpiles = Stockpiles::new();
+  let mut brain = Brain::standard();
+  let mut growth = Growth::new(seed);
+  let mut orders = Vec::new();
+  let mut entityindex = 0;
+  let mut camera = None;
+
+  loop {
+    let line = next()?;
+    let t: Vec<&str> = line.split_whitespace().collect();
+    match t.first() {
+      Some(&"entityindex") => { entityindex = parse(t.get(1), "entity index")?; }
+      Some(&"wall") => {
+        let e: u64 = parse(t.get(1), "wall entity id")?;
+        let id: u32 = parse(t.get(2), "wall id")?;
+        let x: i32 = parse(t.get(3), "wall x")?;
+        let y: i32 = parse(t.get(4), "wall y")?;
+        if !map.check_bounds(x, y) { return Err(bad_save("wall out of bounds")); }
+        let w = Wall::new(id, x, y, 1.0, e);
+        map.build_layer[getmapvecidx(x, y, width)] = Some(Box::new(w));
+        entities.insert_building(w);
+      }
+      Some(&"door") => {
+        let e: u64 = parse(t.get(1), "door entity id")?;
+        let id: u32 = parse(t.get(2), "door id")?;
+        let x: i32 = parse(t.get(3), "door x")?;
+        let y: i32 = parse(t.get(4), "door y")?;
+        let mut d = Door::new(id, x, y, 1.0, e);
+        d.setlocked(parse(t.get(5), "door lock")?);
+        if !map.check_bounds(x, y) { return Err(bad_save("door out of bounds")); }
+        map.build_layer[getmapvecidx(x, y, width)] = Some(Box::new(d));
+        entities.insert_building(d);
+      }
+      Some(&"furniture") => {
+        let e: u64 = parse(t.get(1), "furniture entity id")?;
+        let id: u32 = parse(t.get(2), "furniture id")?;
+        let x: i32 = parse(t.get(3), "furniture x")?;
+        let y: i32 = parse(t.get(4), "furniture y")?;
+        let service = match t.get(5).and_then(|n| Need::from_name(n)) {
+          Some(n) => n,
+          None => { return Err(bad_save("bad furniture need")); }
+        };
+        if !map.check_bounds(x, y) { return Err(bad_save("furniture out of bounds")); }
+        let f = Furniture::new(id, x, y, 1.0, e, service);
+        map.build_layer[getmapvecidx(x, y, width)] = Some(Box::new(f));
+        entities.insert_building(f);
+      }
+      Some(&"blueprint") => {
+        let e: u64 = parse(t.get(1), "blueprint entity id")?;
+        let id: u32 = parse(t.get(2), "blueprint id")?;
+        let x: i32 = parse(t.get(3), "blueprint x")?;
+        let y: i32 = parse(t.get(4), "blueprint y")?;
+        let mut b = Blueprint::new(id, x, y, 1.0, e);
+        b.progress = parse(t.get(5), "blueprint progress")?;
+        if !map.check_bounds(x, y) { return Err(bad_save("blueprint out of bounds")); }
+        map.build_layer[getmapvecidx(x, y, width)] = Some(Box::new(b));
+        entities.insert_building(b);
+      }
+      Some(&"actor") => {
+        match Actor::deserialize(&t[1..]) {
+          Some(a) => entities.insert_actor(a),
+          None => { return Err(bad_save("bad actor")); }
+        }
+      }
+      Some(&"job") => {
+        let kind = match t.get(1).and_then(|k| JobKind::from_name(k)) {
+          Some(k) => k,
+          None => { return Err(bad_save("bad job kind")); }
+        };
+        let x: i32 = parse(t.get(2), "job x")?;
+        let y: i32 = parse(t.get(3), "job y")?;
+        let progress: f32 = parse(t.get(4), "job progress")?;
+        jobs.restore(kind, x, y, progress);
+      }
+      Some(&"order") => {
+        let id: u64 = parse(t.get(1), "order actor")?;
+        let x: i32 = parse(t.get(2), "order x")?;
+        let y: i32 = parse(t.get(3), "order y")?;
+        orders.push((id, (x, y)));
+      }
+      Some(&"haul") => {
+        let x: i32 = parse(t.get(1), "haul x")?;
+        let y: i32 = parse(t.get(2), "haul y")?;
+        let kind = match t.get(3).and_then(|k| ItemKind::from_name(k)) {
+          Some(k) => k,
+          None => { return Err(bad_save("bad haul kind")); }
+        };
+        let count: u32 = parse(t.get(4), "haul count")?;
+        let tox: i32 = parse(t.get(5), "haul destination x")?;
+        let toy: i32 = parse(t.get(6), "haul destination y")?;
+        if !map.check_bounds(x, y) || !map.check_bounds(tox, toy) { return Err(bad_save("haul out of bounds")); }
+        jobs.restore_haul((x, y), kind, count, (tox, toy));
+      }
+      Some(&"items") => {
+        let x: i32 = parse(t.get(1), "items x")?;
+        let y: i32 = parse(t.get(2), "items y")?;
+        let kind = match t.get(3).and_then(|k| ItemKind::from_name(k)) {
+          Some(k) => k,
+          None => { return Err(bad_save("bad item kind")); }
+        };
+        let count: u32 = parse(t.get(4), "item count")?;
+        if !map.check_bounds(x, y) { return Err(bad_save("items out of bounds")); }
+        map.drop_items(x, y, kind, count);
+      }
+      Some(&"plant") => {
+        let x: i32 = parse(t.get(1), "plant x")?;
+        let y: i32 = parse(t.get(2), "plant y")?;
+        let kind = match t.get(3).and_then(|k| PlantKind::from_name(k)) {
+          Some(k) => k,
+          None => { return Err(bad_save("bad plant kind")); }
+        };
+        let stage: u8 = parse(t.get(4), "plant stage")?;
+        if stage >= STAGES { return Err(bad_save("bad plant stage")); }
+        if !map.check_bounds(x, y) { return Err(bad_save("plant out of bounds")); }
+        let mut p = Plant::new(kind, stage);
+        p.growth = parse(t.get(5), "plant growth")?;
+        map.plants.insert((x, y), p); // The saved costmap already has it in
+      }
+      Some(&"stockpile") => {
+        let id: u64 = parse(t.get(1), "stockpile id")?;
+        let mut accepts = Vec::new();
+        for k in t.get(2).unwrap_or(&"").split(',').filter(|k| *k != "-") {
+          match ItemKind::from_name(k) {
+            Some(k) => accepts.push(k),
+            None => { return Err(bad_save("bad stockpile kind")); }
+          }
+        }
+        let mut tiles = Vec::new();
+        for xy in t.iter().skip(3) {
+          let mut c = xy.split(',');
+          tiles.push((parse(c.next(), "stockpile x")?, parse(c.next(), "stockpile y")?));
+        }
+        stockpiles.restore(id, tiles, accepts);
+      }
+      Some(&"growth") => {
+        growth = match Growth::deserialize(&t[1..]) {
+          Some(g) => g,
+          None => { return Err(bad_save("bad growth")); }
+        };
+      }
+      Some(&"brain") => {
+        brain.restore(&t[1..]).ok_or_else(|| bad_save("bad brain"))?;
+      }
+      Some(&"camera") => {
+        let x: f32 = parse(t.get(1), "camera x")?;
+        let y: f32 = parse(t.get(2), "camera y")?;
+        let z: i32 = parse(t.get(3), "zoom step")?;
+        camera = Some(((x, y), z));
+      }
+      Some(&"end") => { break; }
+      Some(other) => { return Err(bad_save(&format!("unknown record '{}'", other))); }
+      None => {}
+    }
+  }
+
+  // Walls restore their own ids, so the saved index must not be lower than any of them
+  if entityindex < entities.getindex() {
+    return Err(bad_save("entity index is behind saved entities"));
+  }
+  entities.setindex(entityindex);
+
+  match camera {
+    Some((camera, zoomstep)) => Ok(SaveGame { map, entities, jobs, stockpiles, brain, growth, orders, camera, zoomstep }),
+    None => Err(bad_save("missing camera")),
+  }
+}
+
+fn parse<T: FromStr, S: AsRef<str>>(tok: Option<S>, what: &str) -> SimResult<T> {
+  match tok.and_then(|t| t.as_ref().parse::<T>().ok()) {
+    Some(v) => Ok(v),
+    None => Err(bad_save(&format!("bad {}", what))),
+  }
+}
+
+fn bad_save(msg: &str) -> SimError {
+  SimError::BadSave(msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ai::Colony;
+  use crate::entities::{DOOR_SPRITE, BED_SPRITE};
+
+  #[test]
+  fn test_save_roundtrip() {
+    let tiles = TileRegistry::standard();
+    let mut map = Map::new(&tiles, 77, 40, 30);
+    let mut entities = Entities::new();
+    for i in 0..3 {
+      let w = Wall::new(0, 10 + i, 12, 1.0, entities.getindex());
+      map.set_building_at(10 + i, 12, w, &mut entities).unwrap();
+    }
+    map.clear_building_at(11, 12, &mut entities).unwrap();
+    let d = Door::new(DOOR_SPRITE, 14, 12, 1.0, entities.getindex());
+    map.set_building_at(14, 12, d, &mut entities).unwrap();
+    map.set_locked_at(14, 12, true, &mut entities);
+    let bed = Furniture::new(BED_SPRITE, 16, 12, 1.0, entities.getindex(), Need::Rest);
+    map.set_building_at(16, 12, bed, &mut entities).unwrap();
+    entities.add_actor(Actor::new(0, 20, 15, 1.0));
+    entities.get_actor_by_id(5).unwrap().needs.set(Need::Hunger, 0.25);
+    let mut jobs = Jobs::new();
+    jobs.restore(JobKind::BuildWall, 5, 5, 250.0);
+    let pile = map.nearest_passable(3, 4).unwrap();
+    map.add_items_at(pile.0, pile.1, ItemKind::Stone, 12);
+    let wood = map.nearest_passable(8, 4).unwrap();
+    map.add_items_at(wood.0, wood.1, ItemKind::Wood, 6);
+    jobs.restore_haul(wood, ItemKind::Wood, 6, (pile.0 + 1, pile.1));
+    let mut piles = Stockpiles::new();
+    piles.add(&[pile, (pile.0 + 1, pile.1)], vec![ItemKind::Wood, ItemKind::Food], &map);
+
+    let mut world = World::from_parts(tiles, map, entities, jobs, piles);
+    let dest = world.map.nearest_passable(30, 20).unwrap();
+    assert!(world.entities.get_actor_by_id(5).unwrap().setmovetarget(dest.0, dest.1, &world.map, &mut world.paths));
+    world.growth.update(1234, &mut world.map, &world.tiles);
+    world.brain.update(100, &mut Colony { entities: &mut world.entities, jobs: &mut world.jobs, tiles: &world.tiles, map: &world.map, paths: &mut world.paths, threats: &world.threats });
+
+    let mut first = Vec::new();
+    save_game(&mut first, &world, (100.0, 250.5), 1).unwrap();
+    let loaded = load_game(&first[..], &world.tiles).unwrap();
+
+    let map = &world.map;
+    assert_eq!(loaded.map.costmap, map.costmap);
+    assert_eq!(loaded.entities.getindex(), 6);
+    assert_eq!(loaded.entities.getactors()[0].getentityid(), 5);
+    assert_eq!(loaded.entities.getactors()[0].needs.get(Need::Hunger), 0.25);
+    assert_eq!(loaded.map.get_building(16, 12).unwrap().getservice(), Some(Need::Rest));
+    assert!(loaded.map.get_building(14, 12).unwrap().islocked());
+    assert!(loaded.map.build_layer[loaded.map.getmapvecidx(12, 12)].is_some());
+    assert!(loaded.map.build_layer[loaded.map.getmapvecidx(11, 12)].is_none());
+    assert_eq!(loaded.camera, (100.0, 250.5));
+    assert_eq!(loaded.zoomstep, 1);
+    assert_eq!(loaded.map.get_items_at(pile.0, pile.1).map(|s| (s.kind, s.count)), Some((ItemKind::Stone, 12)));
+    assert!(loaded.stockpiles.stored(pile.0, pile.1, ItemKind::Food));
+    assert!(!map.getplants().is_empty());
+    assert_eq!(loaded.map.getplants(), map.getplants());
+    let haul = loaded.jobs.getjobs().iter().find_map(|j| j.haul.as_ref().map(|h| (j.target, h.kind, h.count, h.to)));
+    assert_eq!(haul, Some((wood, ItemKind::Wood, 6, (pile.0 + 1, pile.1))));
+    assert_eq!(loaded.orders, vec![(5, dest)]);
+    assert_eq!(loaded.growth.serialize(), world.growth.serialize());
+    assert_eq!(loaded.brain.serialize(), world.brain.serialize());
+
+    let (camera, zoomstep) = (loaded.camera, loaded.zoomstep);
+    let world = World::from_save(TileRegistry::standard(), loaded);
+    assert_eq!(world.entities.getactors()[0].getorder(), Some(dest));
+    let mut second = Vec::new();
+    save_game(&mut second, &world, camera, zoomstep).unwrap();
+    assert_eq!(first, second);
+  }
+
+  #[test]
+  fn test_haul_carries_on_after_load() {
+    let mut world = World::new(TileRegistry::standard(), 99, 60, 60, 2, 0);
+    let home = world.entities.getactors()[0].getoccupiedtile();
+    let area: Vec<(i32, i32)> = (0..3).flat_map(|x| (0..2).map(move |y| (home.0 + x, home.1 - 6 + y))).collect();
+    world.stockpiles.add(&area, vec![ItemKind::Wood], &world.map).unwrap();
+    for _ in 0..3000 {
+      world.tick().unwrap();
+      if world.entities.getactors().iter().any(|a| a.carrying.is_some()) { break; }
+    }
+    assert!(world.entities.getactors().iter().any(|a| a.carrying.is_some()));
+
+    let mut out = Vec::new();
+    save_game(&mut out, &world, (0.0, 0.0), 2).unwrap();
+    let tiles = TileRegistry::standard();
+    let loaded = load_game(&out[..], &tiles).unwrap();
+    let mut world = World::from_save(tiles, loaded);
+    assert!(world.jobs.getjobs().iter().any(|j| j.haul.is_some()));
+    for _ in 0..8000 {
+      world.tick().unwrap();
+    }
+    assert!(world.jobs.getjobs().is_empty());
+    for (t, s) in world.map.getitems().iter() {
+      assert_eq!(world.stockpiles.stored(t.0, t.1, s.kind), s.kind == ItemKind::Wood);
+    }
+  }
+
+  #[test]
+  fn test_rejects_other_versions() {
+    let save = format!("{} {}\n", SAVE_MAGIC, SAVE_VERSION + 1);
+    assert!(load_game(save.as_bytes(), &TileRegistry::standard()).is_err());
+  }
+
+  #[test]
+  fn test_rejects_unknown_tiles() {
+    let world = World::new(TileRegistry::standard(), 7, 8, 8, 0, 0);
+    let mut out = Vec::new();
+    save_game(&mut out, &world, (0.0, 0.0), 1).unwrap();
+    assert!(load_game(&out[..], &TileRegistry::new()).is_err());
+    assert!(load_game(&out[..], &world.tiles).is_ok());
+  }
+}
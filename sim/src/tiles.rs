@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+/// What a kind of terrain is to the simulation. The game finds its sprite by name
+pub struct TileDef {
+  pub name: String,
+  pub movecost: usize, // usize::MAX where nothing can walk
+  pub blends: bool,    // Has edge transition sprites, so its alternate is its edge mask
+  pub fertility: f32,  // 0...1, how much grows on it
+}
+
+/// Terrain types by id. Tile ids in maps and saves index into this
+pub struct TileRegistry {
+  defs: Vec<TileDef>,
+  names: HashMap<String, u32>,
+}
+
+// name, move cost, blends, fertility. Same order as the tiles in import.xml
+const STANDARD_TILES: &[(&str, usize, bool, f32)] = &[
+  ("drysand", 2, true, 0.1),
+  ("dirt", 2, true, 0.4),
+  ("grass0", 1, true, 0.5), // Driest grass first
+  ("grass1", 1, true, 0.6),
+  ("grass2", 1, true, 0.7),
+  ("grass3", 1, true, 0.8),
+  ("grass4", 1, true, 0.9),
+  ("grass5", 1, true, 1.0),
+  ("water0", usize::MAX, false, 0.0),
+  ("water1", usize::MAX, false, 0.0),
+  ("deepwater0", usize::MAX, false, 0.0),
+  ("deepwater1", usize::MAX, false, 0.0),
+  ("rock0", usize::MAX, false, 0.0), // Solid mountain
+  ("rock1", usize::MAX, false, 0.0),
+  ("rock2", usize::MAX, false, 0.0),
+];
+
+impl Default for TileRegistry {
+  fn default() -> Self { TileRegistry::new() }
+}
+
+impl TileRegistry {
+  pub fn new() -> Self {
+    TileRegistry { defs: Vec::new(), names: HashMap::new() }
+  }
+
+  /// Every terrain type the map generator uses
+  pub fn standard() -> Self {
+    let mut r = TileRegistry::new();
+    for (name, cost, blends, fertility) in STANDARD_TILES.iter() {
+      r.add(name, *cost, *blends, *fertility);
+    }
+    r
+  }
+
+  /// Register a terrain type and return its id. Adding a name twice replaces the old definition
+  pub fn add(&mut self, name: &str, movecost: usize, blends: bool, fertility: f32) -> u32 {
+    let def = TileDef { name: name.to_string(), movecost, blends, fertility };
+    if let Some(&id) = self.names.get(name) {
+      self.defs[id as usize] = def;
+      return id;
+    }
+    let id = self.defs.len() as u32;
+    self.defs.push(def);
+    self.names.insert(name.to_string(), id);
+    id
+  }
+
+  pub fn get(&self, id: u32) -> Option<&TileDef> { self.defs.get(id as usize) }
+
+  pub fn get_id(&self, name: &str) -> Option<u32> { self.names.get(name).cloned() }
+
+  pub fn len(&self) -> usize { self.defs.len() }
+
+  pub fn is_empty(&self) -> bool { self.defs.is_empty() }
+
+  pub fn iter(&self) -> impl Iterator<Item = (u32, &TileDef)> {
+    self.defs.iter().enumerate().map(|(i, d)| (i as u32, d))
+  }
+}
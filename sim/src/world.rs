@@ -0,0 +1,157 @@
+use crate::TILESIZE;
+use crate::clock::TICK_MS;
+use crate::entities::{Entities, Actor};
+use crate::jobs::Jobs;
+use crate::items::{ItemKind, Stockpiles};
+use crate::map::Map;
+use crate::map::pathservice::PathService;
+use crate::map::flowfield::FlowFields;
+use crate::needs;
+use crate::plants::Growth;
+use crate::ai::{Brain, Colony};
+use crate::tiles::TileRegistry;
+use crate::error::SimResult;
+use crate::save::SaveGame;
+
+const PATH_THREADS: usize = 2;
+// What a new colony starts with, piled up next to the colonists
+const SUPPLIES: [(ItemKind, u32); 3] = [(ItemKind::Wood, 100), (ItemKind::Stone, 50), (ItemKind::Food, 60)];
+
+/// Everything the simulation steps: the map, what is on it and the work queued for it
+pub struct World {
+  pub tiles: TileRegistry,
+  pub map: Map,
+  pub entities: Entities,
+  pub jobs: Jobs,
+  pub stockpiles: Stockpiles,
+  pub paths: PathService,
+  pub flows: FlowFields,
+  pub brain: Brain,
+  pub growth: Growth,
+  pub threats: Vec<(i32, i32)>, // Tiles actors keep away from, e.g. something hostile
+  // Block on every path search at the start of the next tick, so a run repeats exactly however
+  // fast the workers are. For tests and the command line, a long search would stall the game
+  pub waitforpaths: bool,
+}
+
+impl World {
+  /// Generate a map and put `colonists` actors with sprite `sprite` and their supplies down near
+  /// the middle of it
+  pub fn new(tiles: TileRegistry, seed: u32, width: i32, height: i32, colonists: i32, sprite: u32) -> Self {
+    let mut map = Map::new(&tiles, seed, width, height);
+    let mut entities = Entities::new();
+    let mut taken: Vec<(i32, i32)> = Vec::new();
+    for i in 0..colonists {
+      let (cx, cy) = (map.width / 2 + (i * 2) - colonists + 1, map.height / 2);
+      let (x, y) = map.nearest_passable(cx, cy).unwrap_or((cx, cy));
+      if taken.contains(&(x, y)) { continue; }
+      taken.push((x, y));
+      entities.add_actor(Actor::new(sprite, x, y, 1.0));
+    }
+    let (cx, cy) = (map.width / 2, (map.height / 2) + 2);
+    if let Some((x, y)) = map.nearest_passable(cx, cy) {
+      for (kind, count) in SUPPLIES.iter() {
+        map.drop_items(x, y, *kind, *count);
+      }
+    }
+    World::from_parts(tiles, map, entities, Jobs::new(), Stockpiles::new())
+  }
+
+  /// Put a world back together, e.g. from a save. Random rolls start from the map's seed
+  pub fn from_parts(tiles: TileRegistry, map: Map, entities: Entities, jobs: Jobs, stockpiles: Stockpiles) -> Self {
+    let mut brain = Brain::standard();
+    brain.setseed(map.seed);
+    let growth = Growth::new(map.seed);
+    World { tiles, map, entities, jobs, stockpiles, paths: PathService::new(PATH_THREADS), flows: FlowFields::new(), brain, growth, threats: Vec::new(), waitforpaths: false }
+  }
+
+  /// Rebuild a world from a save. Anything not in the save starts afresh: no threats, flow fields
+  /// or path searches from before, and actors that were waiting on a path ask for it again
+  pub fn from_save(tiles: TileRegistry, s: SaveGame) -> Self {
+    let mut world = World::from_parts(tiles, s.map, s.entities, s.jobs, s.stockpiles);
+    world.brain = s.brain;
+    world.growth = s.growth;
+    for (id, (x, y)) in s.orders {
+      if let Some(a) = world.entities.get_actor_by_id(id) {
+        a.setmovetarget(x, y, &world.map, &mut world.paths);
+      }
+    }
+    world
+  }
+
+  /// Advance the simulation by one fixed step of TICK_MS. Actors keep waiting on paths that
+  /// haven't been found yet, unless waitforpaths is set
+  pub fn tick(&mut self) -> SimResult<()> {
+    let found = if self.waitforpaths { self.paths.wait() } else { self.paths.poll() };
+    self.entities.collect_paths(found, &mut self.paths, &self.map);
+    self.entities.update(TICK_MS, TILESIZE as f32, &self.map);
+    self.jobs.update(TICK_MS, &mut self.entities, &mut self.map, &mut self.paths)?;
+    self.jobs.plan_hauls(TICK_MS, &self.stockpiles, &self.map);
+    needs::update(TICK_MS, &mut self.entities, &mut self.jobs, &self.tiles, &mut self.map);
+    let mut colony = Colony { entities: &mut self.entities, jobs: &mut self.jobs, tiles: &self.tiles, map: &self.map, paths: &mut self.paths, threats: &self.threats };
+    self.brain.update(TICK_MS, &mut colony);
+    self.growth.update(TICK_MS, &mut self.map, &self.tiles);
+    let changed = self.map.take_changes();
+    if !changed.is_empty() {
+      self.entities.handle_map_changes(&changed, &self.map, &mut self.paths);
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::jobs::JobKind;
+  use crate::map::Pos;
+  use crate::entities::Entity;
+
+  #[test]
+  fn test_headless_colony() {
+    let tiles = TileRegistry::standard();
+    let mut world = World::new(tiles, 1234, 120, 80, 3, 0);
+    assert_eq!(world.entities.getactors().len(), 3);
+
+    // A short wall somewhere the colonists can walk to
+    let home = Pos::from(world.entities.getactors()[0].getoccupiedtile());
+    let mut placed = 0;
+    for (x, y) in (0..8).map(|i| (home.0 - 4 + i, home.1 + 4)) {
+      if !world.map.is_reachable(&home, &Pos(x, y)) { continue; }
+      if world.jobs.place_blueprint(JobKind::BuildWall, x, y, &mut world.entities, &mut world.map) {
+        placed = placed + 1;
+      }
+    }
+    assert!(placed > 0);
+
+    for _ in 0..5000 {
+      world.tick().unwrap();
+    }
+    assert!(world.jobs.getjobs().is_empty());
+    let walls = world.entities.getbuildings().values().filter(|b| !b.isblueprint()).count();
+    assert_eq!(walls, placed);
+  }
+
+  #[test]
+  fn test_hauling_to_stockpile() {
+    let mut world = World::new(TileRegistry::standard(), 99, 60, 60, 2, 0);
+    let total = |w: &World, kind: ItemKind| -> u32 {
+      w.map.getitems().values().filter(|s| s.kind == kind).map(|s| s.count).sum()
+    };
+    let wood = total(&world, ItemKind::Wood);
+    assert!(wood > 0);
+
+    // A wood-only stockpile a little way off
+    let home = world.entities.getactors()[0].getoccupiedtile();
+    let area: Vec<(i32, i32)> = (0..3).flat_map(|x| (0..2).map(move |y| (home.0 + x, home.1 - 6 + y))).collect();
+    world.stockpiles.add(&area, vec![ItemKind::Wood], &world.map).unwrap();
+    for _ in 0..8000 {
+      world.tick().unwrap();
+    }
+    assert!(world.jobs.getjobs().is_empty());
+    assert!(world.entities.getactors().iter().all(|a| a.carrying.is_none()));
+    assert_eq!(total(&world, ItemKind::Wood), wood);
+    for (t, s) in world.map.getitems().iter() {
+      assert_eq!(world.stockpiles.stored(t.0, t.1, s.kind), s.kind == ItemKind::Wood);
+    }
+  }
+}
@@ -0,0 +1,91 @@
+use std::fs::File;
+use std::io::BufReader;
+use xml::reader::{EventReader, XmlEvent};
+
+const DEFAULT_MAP_WIDTH: i32 = 300;
+const DEFAULT_MAP_HEIGHT: i32 = 300;
+const MIN_MAP_SIZE: i32 = 16;
+
+/// Game settings. Read from `config.xml`, then overridden by command line flags
+#[derive(Clone)]
+pub struct Config {
+  pub seed: Option<u32>,
+  pub width: i32,
+  pub height: i32,
+}
+
+impl Config {
+  pub fn new() -> Self {
+    Config { seed: None, width: DEFAULT_MAP_WIDTH, height: DEFAULT_MAP_HEIGHT }
+  }
+
+  /// Load settings from an xml file. A missing or unreadable file just leaves the defaults
+  pub fn load(path: &str) -> Self {
+    let mut config = Config::new();
+    let file = match File::open(path) {
+      Ok(f) => BufReader::new(f),
+      Err(_) => { return config; }
+    };
+
+    let mut element = String::new();
+    for e in EventReader::new(file) {
+      match e {
+        Ok(XmlEvent::StartElement { name, .. }) => { element = name.to_string(); }
+        Ok(XmlEvent::EndElement { .. }) => { element.clear(); }
+        Ok(XmlEvent::Characters(e)) => {
+          match element.as_ref() {
+            "seed" => { config.seed = Config::parse_seed(&e); }
+            "width" => { config.width = Config::parse_size(&e).unwrap_or(config.width); }
+            "height" => { config.height = Config::parse_size(&e).unwrap_or(config.height); }
+            _ => {}
+          }
+        }
+        Err(e) => {
+          println!("Error reading {}: {}", path, e);
+          break;
+        }
+        _ => {}
+      }
+    }
+    config
+  }
+
+  /// Apply command line flags, e.g. `--seed 12345 --width 400 --height 200`
+  pub fn apply_args<I: Iterator<Item = String>>(&mut self, mut args: I) {
+    while let Some(arg) = args.next() {
+      match arg.as_ref() {
+        "--seed" => {
+          match args.next().and_then(|s| Config::parse_seed(&s)) {
+            Some(seed) => { self.seed = Some(seed); }
+            None => { println!("--seed expects a number between 0 and {}", u32::max_value()); }
+          }
+        }
+        "--width" => {
+          match args.next().and_then(|s| Config::parse_size(&s)) {
+            Some(w) => { self.width = w; }
+            None => { println!("--width expects a number of at least {}", MIN_MAP_SIZE); }
+          }
+        }
+        "--height" => {
+          match args.next().and_then(|s| Config::parse_size(&s)) {
+            Some(h) => { self.height = h; }
+            None => { println!("--height expects a number of at least {}", MIN_MAP_SIZE); }
+          }
+        }
+        _ => { println!("Unknown argument: {}", arg); }
+      }
+    }
+  }
+
+  pub fn parse_seed(s: &str) -> Option<u32> {
+    s.trim().parse::<u32>().ok()
+  }
+
+  fn parse_size(s: &str) -> Option<i32> {
+    match s.trim().parse::<i32>() {
+      Ok(n) if n >= MIN_MAP_SIZE => Some(n),
+      _ => None,
+    }
+  }
+}
+
@@ -5,7 +5,9 @@ extern crate rand;
 extern crate xml;
 use ggez::*;
 
+mod config;
 mod states;
+use crate::config::Config;
 use crate::states::StateManager;
 
 fn main() {
@@ -13,12 +15,15 @@ fn main() {
     .window_setup(conf::WindowSetup::default().title("Rustworld"))
     .window_mode(conf::WindowMode::default().dimensions(1920, 1080));
 
+  let mut config = Config::load("config.xml");
+  config.apply_args(std::env::args().skip(1));
+
   let ctx = &mut cb.build().unwrap();
 
   graphics::set_screen_coordinates(ctx, graphics::Rect::new_i32(0, 0, 1920, 1080))
     .expect("Failed");
 
-  let mut state = StateManager::new(ctx);
+  let mut state = StateManager::new(ctx, config);
   if let Err(e) = event::run(ctx, &mut state) {
     println!("Error running: {}", e);
   }
@@ -2,7 +2,8 @@ use ggez::graphics::Point2;
 use ggez::{graphics, Context, GameResult};
 use std::time::Duration;
 use ggez::event::{MouseButton};
-use crate::states::play_state::PlayState;
+use crate::states::newgame_state::NewGameState;
+use crate::config::Config;
 
 use crate::states::{Assets, State, Transition};
 
@@ -29,10 +30,11 @@ pub struct IntroState {
   fade_in: f32,
   authorfade: f32,
   afader: f32,
+  config: Config,
 }
 
 impl IntroState {
-  pub fn new(ctx: &mut Context, assets: &Assets) -> GameResult<Self> {
+  pub fn new(ctx: &mut Context, assets: &Assets, config: Config) -> GameResult<Self> {
     let intro_text = graphics::Text::new(ctx, "Rustworld", assets.get_font("rns")?)?;
     let author_text = graphics::Text::new(ctx, "by Brad Hopper", assets.get_font("basic")?)?;
 
@@ -44,6 +46,7 @@ impl IntroState {
       fade_in: 0.0,
       authorfade: 0.0,
       afader: 0.0,
+      config,
     })
   }
 
@@ -56,7 +59,7 @@ impl State for IntroState {
   fn update(&mut self, ctx: &mut Context, assets: &Assets, dt: Duration,) -> GameResult<Transition> {
     if self.hit_any_key {
       graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0),)?;
-      return Ok(Transition::Swap(Box::new(PlayState::new(ctx, assets)?)));
+      return Ok(Transition::Swap(Box::new(NewGameState::new(ctx, assets, self.config.clone())?)));
     }
       
     if self.fade_in < FADE_TIME {
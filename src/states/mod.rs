@@ -1,7 +1,7 @@
-use ggez::{graphics, GameResult, Context, timer, nalgebra as na};
+use ggez::{graphics, GameError, GameResult, Context, timer, nalgebra as na};
 use ggez::graphics::Drawable;
 use ggez::graphics::spritebatch::SpriteBatch;
-use ggez::event::{EventHandler, MouseState, MouseButton};
+use ggez::event::{EventHandler, MouseState, MouseButton, Keycode, Mod};
 use std::collections::HashMap;
 use std::time::Duration;
 use std::fs::File;
@@ -10,7 +10,10 @@ use xml::reader::{EventReader, XmlEvent};
 
 pub mod play_state;
 pub mod intro_state;
+pub mod newgame_state;
 use crate::states::intro_state::IntroState;
+use crate::config::Config;
+use rustworld_sim::TILESIZE;
 
 pub trait DurationExt: Sized {
   fn as_subsec_millis(&self) -> f64;
@@ -24,7 +27,7 @@ impl DurationExt for Duration {
 
 struct Asset {
     spritebatch: SpriteBatch,
-    alternates: Vec<SpriteBatch>,
+    alternates: Vec<Option<SpriteBatch>>, // None where the image couldn't be loaded
 }
 
 impl Asset {
@@ -32,7 +35,7 @@ impl Asset {
     Asset { spritebatch: sprite, alternates: Vec::new() }
   }
   
-  fn addalternate(&mut self, sprite: SpriteBatch) {
+  fn addalternate(&mut self, sprite: Option<SpriteBatch>) {
     self.alternates.push(sprite);
   }
 }
@@ -41,9 +44,12 @@ pub struct Assets {
   images: HashMap<u32, Asset>,
   actorimages: HashMap<u32, Asset>,
   buildingimages: HashMap<u32, Asset>,
+  itemimages: HashMap<u32, Asset>,
+  plantimages: HashMap<u32, Asset>,
   names: HashMap<String, u32>,
   font: HashMap<String, graphics::Font>,
   lines: Vec<((f32, f32), (f32, f32))>,
+  texts: Vec<(String, (f32, f32), graphics::Color)>,
 }
 
 impl Assets {
@@ -52,9 +58,12 @@ impl Assets {
       images: HashMap::new(),
       actorimages: HashMap::new(),
       buildingimages: HashMap::new(),
+      itemimages: HashMap::new(),
+      plantimages: HashMap::new(),
       names: HashMap::new(),
       font: HashMap::new(),
       lines: Vec::new(),
+      texts: Vec::new(),
     }
   }
 
@@ -67,8 +76,14 @@ impl Assets {
     Ok(())
   }
   
-  pub fn add_alt_image(&mut self, id: &u32, image: graphics::Image) -> GameResult<()> {
-    self.images.get_mut(id).unwrap().addalternate(SpriteBatch::new(image));
+  /// A missing image keeps its place, so the alternates after it keep their ids
+  pub fn add_alt_image(&mut self, id: &u32, image: Option<graphics::Image>) -> GameResult<()> {
+    let sb = image.map(|i| {
+      let mut sb = SpriteBatch::new(i);
+      sb.set_blend_mode(Some(graphics::BlendMode::Alpha));
+      sb
+    });
+    self.images.get_mut(id).unwrap().addalternate(sb);
     Ok(())
   }
 
@@ -79,12 +94,40 @@ impl Assets {
   }
 
   pub fn add_actor_alt_image(&mut self, id: &u32, image: graphics::Image) -> GameResult<()> {
-    self.actorimages.get_mut(id).unwrap().addalternate(SpriteBatch::new(image));
+    self.actorimages.get_mut(id).unwrap().addalternate(Some(SpriteBatch::new(image)));
     Ok(())
   }
 
   pub fn add_building_image(&mut self, name: &str, id: &u32, image: graphics::Image) -> GameResult<()> {
-    self.buildingimages.insert(*id, Asset::new(SpriteBatch::new(image)));
+    let mut sb = SpriteBatch::new(image);
+    sb.set_blend_mode(Some(graphics::BlendMode::Alpha)); // Blueprints draw see-through
+    self.buildingimages.insert(*id, Asset::new(sb));
+    self.names.insert(name.to_string(), *id);
+    Ok(())
+  }
+
+  pub fn add_building_alt_image(&mut self, id: &u32, image: Option<graphics::Image>) -> GameResult<()> {
+    let sb = image.map(|i| {
+      let mut sb = SpriteBatch::new(i);
+      sb.set_blend_mode(Some(graphics::BlendMode::Alpha));
+      sb
+    });
+    self.buildingimages.get_mut(id).unwrap().addalternate(sb);
+    Ok(())
+  }
+
+  pub fn add_item_image(&mut self, name: &str, id: &u32, image: graphics::Image) -> GameResult<()> {
+    let mut sb = SpriteBatch::new(image);
+    sb.set_blend_mode(Some(graphics::BlendMode::Alpha));
+    self.itemimages.insert(*id, Asset::new(sb));
+    self.names.insert(name.to_string(), *id);
+    Ok(())
+  }
+
+  pub fn add_plant_image(&mut self, name: &str, id: &u32, image: graphics::Image) -> GameResult<()> {
+    let mut sb = SpriteBatch::new(image);
+    sb.set_blend_mode(Some(graphics::BlendMode::Alpha));
+    self.plantimages.insert(*id, Asset::new(sb));
     self.names.insert(name.to_string(), *id);
     Ok(())
   }
@@ -94,9 +137,19 @@ impl Assets {
     Ok(&img.unwrap().spritebatch)
   }
 
+  pub fn get_alt_count(&self, id: &u32) -> usize {
+    match self.images.get(id) {
+      Some(a) => a.alternates.len(),
+      None => 0,
+    }
+  }
+
   pub fn get_alt_image(&self, id: &u32, altid: usize) -> GameResult<&SpriteBatch> {
-    let img = self.images.get(id).unwrap().alternates.get(altid);
-    Ok(img.unwrap())
+    let a = self.images.get(id).unwrap();
+    match a.alternates.get(altid) {
+      Some(Some(sb)) => Ok(sb),
+      _ => Ok(&a.spritebatch),
+    }
   }
 
   pub fn get_actor_image(&self, id: &u32) -> GameResult<&SpriteBatch> {
@@ -110,18 +163,23 @@ impl Assets {
   }
 
   pub fn get_id(&self, name: String) -> GameResult<u32> {
-    let id = self.names.get(&name);
-    let a = *id.unwrap();
-    Ok(*id.unwrap())
-
+    match self.names.get(&name) {
+      Some(id) => Ok(*id),
+      None => Err(GameError::ResourceNotFound(name, Vec::new())),
+    }
   }
 
   pub fn draw_image(&mut self, id: &u32, p: graphics::DrawParam) { //
     self.images.get_mut(id).unwrap().spritebatch.add(p);
   }
 
+  /// Falls back to the base sprite if that alternate's image couldn't be loaded
   pub fn draw_alt_image(&mut self, id: &u32, alt: usize, p: graphics::DrawParam) {
-    self.images.get_mut(id).unwrap().alternates.get_mut(alt).unwrap().add(p);
+    let a = self.images.get_mut(id).unwrap();
+    match a.alternates.get_mut(alt) {
+      Some(Some(sb)) => { sb.add(p); }
+      _ => { a.spritebatch.add(p); }
+    }
   }
 
   pub fn draw_actor_image(&mut self, id: &u32, p: graphics::DrawParam) {
@@ -132,10 +190,32 @@ impl Assets {
     self.buildingimages.get_mut(id).unwrap().spritebatch.add(p);
   }
 
+  /// Falls back to the base sprite if the building has no such alternate
+  pub fn draw_building_alt_image(&mut self, id: &u32, alt: usize, p: graphics::DrawParam) {
+    let a = self.buildingimages.get_mut(id).unwrap();
+    match a.alternates.get_mut(alt) {
+      Some(Some(sb)) => { sb.add(p); }
+      _ => { a.spritebatch.add(p); }
+    }
+  }
+
+  pub fn draw_item_image(&mut self, id: &u32, p: graphics::DrawParam) {
+    self.itemimages.get_mut(id).unwrap().spritebatch.add(p);
+  }
+
+  pub fn draw_plant_image(&mut self, id: &u32, p: graphics::DrawParam) {
+    self.plantimages.get_mut(id).unwrap().spritebatch.add(p);
+  }
+
   pub fn draw_UI_line(&mut self, line: ((f32, f32), (f32, f32))) {
     self.lines.push(line);
   }
 
+  /// Queue text to draw over everything else this frame, at screen position pos
+  pub fn draw_UI_text(&mut self, text: &str, pos: (f32, f32), color: graphics::Color) {
+    self.texts.push((text.to_string(), pos, color));
+  }
+
   pub fn add_font(&mut self, name: &str, font: graphics::Font) -> GameResult<()> {
     self.font.insert(name.to_string(), font);
     Ok(())
@@ -147,6 +227,32 @@ impl Assets {
   }
 }
 
+/// An image, or None with a warning if it can't be loaded. Alternates are optional art: whatever
+/// uses them draws the base sprite instead
+fn load_image(ctx: &mut Context, path: &str) -> Option<graphics::Image> {
+  match graphics::Image::new(ctx, path) {
+    Ok(i) => Some(i),
+    Err(e) => {
+      println!("Couldn't load {}: {}", path, e);
+      None
+    }
+  }
+}
+
+/// A stand-in for a sprite whose image is missing, so the sprite keeps its id and the game still
+/// runs. It shows up bright pink, which is hard to miss
+fn placeholder(ctx: &mut Context) -> GameResult<graphics::Image> {
+  graphics::Image::solid(ctx, TILESIZE as u16, graphics::Color::new(1.0, 0.0, 1.0, 1.0))
+}
+
+/// A sprite's image, or the placeholder if it can't be loaded
+fn sprite_image(ctx: &mut Context, path: &str) -> GameResult<graphics::Image> {
+  match load_image(ctx, path) {
+    Some(image) => Ok(image),
+    None => placeholder(ctx),
+  }
+}
+
 pub enum Transition {
   None,
   Push(Box<dyn State>),
@@ -162,6 +268,9 @@ pub trait State {
   fn mouse_button_up_event(&mut self, _ctx: &mut Context, _button: MouseButton, _x: i32, _y: i32,) {}
   fn mouse_motion_event(&mut self, _ctx: &mut Context, _button: MouseState, _x: i32, _y: i32, _xrel: i32, _yrel: i32) {}
   fn mouse_wheel_event(&mut self, _ctx: &mut Context, _x: i32, _y: i32) {}
+  fn key_down_event(&mut self, _ctx: &mut Context, _keycode: Keycode, _keymod: Mod, _repeat: bool) {}
+  fn key_up_event(&mut self, _ctx: &mut Context, _keycode: Keycode, _keymod: Mod, _repeat: bool) {}
+  fn text_input_event(&mut self, _ctx: &mut Context, _text: String) {}
   fn focus_event(&mut self, _ctx: &mut Context, _gained: bool) {}
   fn quit_event(&mut self, _ctx: &mut Context) -> bool { false }
 
@@ -174,9 +283,9 @@ pub struct StateManager {
 }
 
 impl StateManager {
-  pub fn new(ctx: &mut Context) -> StateManager {
+  pub fn new(ctx: &mut Context, config: Config) -> StateManager {
     let mut assets = StateManager::initialize_assets(ctx).unwrap();
-    let state = Box::new(IntroState::new(ctx, &assets).unwrap());
+    let state = Box::new(IntroState::new(ctx, &assets, config).unwrap());
 
     StateManager {
       running: true,
@@ -194,7 +303,7 @@ impl StateManager {
 
     enum Elements { name, location, alternate };
 
-    enum SpriteTypes { tile, actor, building, }
+    enum SpriteTypes { tile, actor, building, item, plant, }
 
     enum Types { sprite, font, };
 
@@ -252,6 +361,12 @@ impl StateManager {
 
                       is.push(SpriteStruct::new(&name, &e.to_string(), SpriteTypes::building));
                     }
+                    SpriteTypes::item => {
+                      is.push(SpriteStruct::new(&name, &e.to_string(), SpriteTypes::item));
+                    }
+                    SpriteTypes::plant => {
+                      is.push(SpriteStruct::new(&name, &e.to_string(), SpriteTypes::plant));
+                    }
                   }
                 }
                 Types::font => {
@@ -263,9 +378,12 @@ impl StateManager {
             Elements::alternate => {
               match typ {
                 Types::sprite => {
-                      is.get_mut((&id - 1) as usize).unwrap().altsprites.push(e.to_string());
+                  // Alternates belong to the sprite whose <location> came just before them
+                  match is.last_mut() {
+                    Some(s) => s.altsprites.push(e.to_string()),
+                    None => println!("Alternate {} has no sprite to belong to", e),
                   }
-                
+                }
                 _ => {}
               }
             }
@@ -281,6 +399,8 @@ impl StateManager {
             "tiles" => { sprtyp = SpriteTypes::tile; }
             "actors" => { sprtyp = SpriteTypes::actor; }
             "buildings" => { sprtyp = SpriteTypes::building; }
+            "items" => { sprtyp = SpriteTypes::item; }
+            "plants" => { sprtyp = SpriteTypes::plant; }
             _ => { }
           }
         }
@@ -297,29 +417,39 @@ impl StateManager {
     let mut id1 = 0;
     let mut id2 = 0;
     let mut id3 = 0;
+    let mut id4 = 0;
+    let mut id5 = 0;
     for mut i in is {
       match i.typ {
         SpriteTypes::tile => { 
-          assets.add_image(&i.name, &id1, graphics::Image::new(ctx, &i.sprite).unwrap()); 
-          for j in i.altsprites.iter_mut() {
-            assets.add_alt_image(&id1, graphics::Image::new(ctx, &j).unwrap());
+          assets.add_image(&i.name, &id1, sprite_image(ctx, &i.sprite)?);
+          for j in i.altsprites.iter() {
+            assets.add_alt_image(&id1, load_image(ctx, j));
           }
           id1 = id1 + 1;
         }
         SpriteTypes::actor => { 
-          assets.add_actor_image(&i.name, &id2, graphics::Image::new(ctx, &i.sprite).unwrap());
+          assets.add_actor_image(&i.name, &id2, sprite_image(ctx, &i.sprite)?);
           for j in i.altsprites.iter_mut() {
             // Add alts
           }
           id2 = id2 + 1;
         }
         SpriteTypes::building => { 
-          assets.add_building_image(&i.name, &id3, graphics::Image::new(ctx, &i.sprite).unwrap()); 
-          for j in i.altsprites.iter_mut() {
-            // Add alts
+          assets.add_building_image(&i.name, &id3, sprite_image(ctx, &i.sprite)?);
+          for j in i.altsprites.iter() {
+            assets.add_building_alt_image(&id3, load_image(ctx, j));
           }
           id3 = id3 + 1;
         }
+        SpriteTypes::item => {
+          assets.add_item_image(&i.name, &id4, sprite_image(ctx, &i.sprite)?);
+          id4 = id4 + 1;
+        }
+        SpriteTypes::plant => {
+          assets.add_plant_image(&i.name, &id5, sprite_image(ctx, &i.sprite)?);
+          id5 = id5 + 1;
+        }
       }
     }
 
@@ -398,16 +528,27 @@ impl EventHandler for StateManager {
     // Tiles
     for (_, (_, spr)) in self.assets.images.iter_mut().enumerate() {
       graphics::draw_ex(ctx, &spr.spritebatch, p)?;
-      for (_, a) in spr.alternates.iter_mut().enumerate() {
+      for a in spr.alternates.iter_mut().flatten() {
         graphics::draw_ex(ctx, a, p)?;
         a.clear();
       }
       spr.spritebatch.clear();
     }
 
+    for (_, (_, spr)) in self.assets.plantimages.iter_mut().enumerate() {
+      graphics::draw_ex(ctx, &spr.spritebatch, p)?;
+      spr.spritebatch.clear();
+    }
+
+    // Loose items lie on the ground, under anyone walking over them
+    for (_, (_, spr)) in self.assets.itemimages.iter_mut().enumerate() {
+      graphics::draw_ex(ctx, &spr.spritebatch, p)?;
+      spr.spritebatch.clear();
+    }
+
     for (_, (_, spr)) in self.assets.actorimages.iter_mut().enumerate() {
       graphics::draw_ex(ctx, &spr.spritebatch, p)?;
-      for (_, a) in spr.alternates.iter_mut().enumerate() {
+      for a in spr.alternates.iter_mut().flatten() {
         graphics::draw_ex(ctx, a, p)?;
         a.clear();
       }
@@ -416,7 +557,7 @@ impl EventHandler for StateManager {
 
     for (_, (_, spr)) in self.assets.buildingimages.iter_mut().enumerate() {
       graphics::draw_ex(ctx, &spr.spritebatch, p)?;
-      for (_, a) in spr.alternates.iter_mut().enumerate() {
+      for a in spr.alternates.iter_mut().flatten() {
         graphics::draw_ex(ctx, a, p)?;
         a.clear();
       }
@@ -430,6 +571,16 @@ impl EventHandler for StateManager {
     }
     self.assets.lines.clear();
 
+    for (t, pos, color) in self.assets.texts.iter() {
+      let text = graphics::Text::new(ctx, t, self.assets.get_font("basic")?)?;
+      graphics::set_color(ctx, *color)?;
+      graphics::draw(ctx, &text, na::Point2::new(pos.0, pos.1), 0.0)?;
+    }
+    if !self.assets.texts.is_empty() {
+      graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+    }
+    self.assets.texts.clear();
+
     graphics::present(ctx);
     timer::sleep(Duration::from_secs(0));
     Ok(())
@@ -459,6 +610,24 @@ impl EventHandler for StateManager {
     }
   }
 
+  fn key_down_event(&mut self, ctx: &mut Context, keycode: Keycode, keymod: Mod, repeat: bool) {
+    if let Some(state) = self.states.last_mut() {
+      state.key_down_event(ctx, keycode, keymod, repeat);
+    }
+  }
+
+  fn key_up_event(&mut self, ctx: &mut Context, keycode: Keycode, keymod: Mod, repeat: bool) {
+    if let Some(state) = self.states.last_mut() {
+      state.key_up_event(ctx, keycode, keymod, repeat);
+    }
+  }
+
+  fn text_input_event(&mut self, ctx: &mut Context, text: String) {
+    if let Some(state) = self.states.last_mut() {
+      state.text_input_event(ctx, text);
+    }
+  }
+
   fn focus_event(&mut self, ctx: &mut Context, gained: bool) {
     if let Some(state) = self.states.last_mut() {
       state.focus_event(ctx, gained);
@@ -0,0 +1,95 @@
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+use std::time::Duration;
+use ggez::event::{MouseButton, Keycode, Mod};
+use crate::states::play_state::PlayState;
+use crate::config::Config;
+
+use crate::states::{Assets, State, Transition};
+
+const MAX_SEED_DIGITS: usize = 10;
+
+pub struct NewGameState {
+  title_text: graphics::Text,
+  hint_text: graphics::Text,
+  seed: String,
+  width: i32,
+  height: i32,
+  start: bool,
+}
+
+impl NewGameState {
+  pub fn new(ctx: &mut Context, assets: &Assets, config: Config) -> GameResult<Self> {
+    let title_text = graphics::Text::new(ctx, "New Game", assets.get_font("rns")?)?;
+    let hint_text = graphics::Text::new(ctx, "Type a seed, R for a random one, Enter to start", assets.get_font("basic")?)?;
+    let seed = match config.seed {
+      Some(s) => s,
+      None => rand::random::<u32>(),
+    };
+
+    Ok(NewGameState {
+      title_text,
+      hint_text,
+      seed: seed.to_string(),
+      width: config.width,
+      height: config.height,
+      start: false,
+    })
+  }
+
+  /// Seed currently entered. An empty field falls back to 0
+  pub fn getseed(&self) -> u32 {
+    Config::parse_seed(&self.seed).unwrap_or(0)
+  }
+}
+
+impl State for NewGameState {
+  fn update(&mut self, ctx: &mut Context, assets: &Assets, _dt: Duration,) -> GameResult<Transition> {
+    if self.start {
+      return Ok(Transition::Swap(Box::new(PlayState::new(ctx, assets, self.getseed(), self.width, self.height)?)));
+    }
+    Ok(Transition::None)
+  }
+
+  fn draw(&mut self, ctx: &mut Context, assets: &mut Assets) -> GameResult<()> {
+    let coords = graphics::get_screen_coordinates(ctx);
+    let seed_text = graphics::Text::new(ctx, &format!("Seed: {}    Map: {} x {}", self.seed, self.width, self.height), assets.get_font("basic")?)?;
+
+    let title_dest = Point2::new(coords.w / 2.0 - (self.title_text.width() / 2) as f32, 300.0);
+    let seed_dest = Point2::new(coords.w / 2.0 - (seed_text.width() / 2) as f32, 450.0);
+    let hint_dest = Point2::new(coords.w / 2.0 - (self.hint_text.width() / 2) as f32, 550.0);
+
+    graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0),)?;
+    graphics::draw(ctx, &self.title_text, title_dest, 0.0)?;
+    graphics::draw(ctx, &seed_text, seed_dest, 0.0)?;
+    graphics::set_color(ctx, graphics::Color::new(0.8, 0.8, 0.8, 1.0),)?;
+    graphics::draw(ctx, &self.hint_text, hint_dest, 0.0)?;
+    graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0),)?;
+
+    Ok(())
+  }
+
+  fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, _x: i32, _y: i32) {
+    if button == MouseButton::Left {
+      self.start = true;
+    }
+  }
+
+  fn key_down_event(&mut self, _ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
+    match keycode {
+      Keycode::Return => { self.start = true; }
+      Keycode::Backspace => { self.seed.pop(); }
+      Keycode::R => { self.seed = rand::random::<u32>().to_string(); }
+      _ => {}
+    }
+  }
+
+  fn text_input_event(&mut self, _ctx: &mut Context, text: String) {
+    for c in text.chars().filter(|c| c.is_ascii_digit()) {
+      if self.seed.len() >= MAX_SEED_DIGITS { break; }
+      let mut s = self.seed.clone();
+      s.push(c);
+      if Config::parse_seed(&s).is_some() { self.seed = s; }
+    }
+  }
+}
@@ -1,9 +1,9 @@
 use ggez::graphics::Point2;
-use super::{MAPSIZE_MAX_X, MAPSIZE_MAX_Y, TILESIZE };
+use super::TILESIZE;
 
 const ZOOMLEVELS: usize = 4;
 const ZOOMSTEP: [f32; ZOOMLEVELS] = [0.3, 0.8, 1.0, 1.3];
-const MOVESTEP: f32 = 1.0;
+const MOVESTEP: f32 = 1.0; // Per pixel of mouse drag, so panning is the same at any frame rate
 
 pub struct Camera {
   pub position: Point2,
@@ -19,25 +19,32 @@ pub struct Camera {
 
   min_x: i32,
   min_y: i32,
+
+  mapwidth: i32,
+  mapheight: i32,
 }
 
 impl Camera {
-  pub fn new(ctx: &mut ggez::Context) -> Self {
+  /// scrw and scrh are the window size in pixels
+  pub fn new(scrw: i32, scrh: i32, mapwidth: i32, mapheight: i32) -> Self {
     Camera {
-      position: Point2::new((MAPSIZE_MAX_X as f32 / 2.0) * TILESIZE as f32, (MAPSIZE_MAX_Y as f32 / 2.0) * TILESIZE as f32),
+      position: Point2::new((mapwidth as f32 / 2.0) * TILESIZE as f32, (mapheight as f32 / 2.0) * TILESIZE as f32),
       zoomlevel: 1.0,
-      zoomstep: 9,
+      zoomstep: 2, // ZOOMSTEP[2] == 1.0
 
-      scrX: ctx.conf.window_mode.width as i32,
-      scrY: ctx.conf.window_mode.height as i32,
+      scrX: scrw,
+      scrY: scrh,
 
       tsize: TILESIZE as f32,
 
-      maplimx: ((MAPSIZE_MAX_X * TILESIZE) - ctx.conf.window_mode.width as i32 - TILESIZE),
-      maplimy: ((MAPSIZE_MAX_Y * TILESIZE) - ctx.conf.window_mode.height as i32 - TILESIZE),
+      maplimx: ((mapwidth * TILESIZE) - scrw - TILESIZE),
+      maplimy: ((mapheight * TILESIZE) - scrh - TILESIZE),
+
+      min_x: ((mapwidth * TILESIZE) - scrw - TILESIZE), 
+      min_y: ((mapheight * TILESIZE) - scrh - TILESIZE), 
 
-      min_x: ((MAPSIZE_MAX_X * TILESIZE) - ctx.conf.window_mode.width as i32 - TILESIZE), 
-      min_y: ((MAPSIZE_MAX_Y * TILESIZE) - ctx.conf.window_mode.height as i32 - TILESIZE), 
+      mapwidth,
+      mapheight,
 
     }
   }
@@ -50,6 +57,14 @@ impl Camera {
      ((self.position.y as i32 + y) as f32 / self.tsize) as i32)
   }
 
+/// Screen_To_Map (&self, x: i32, y: i32) -> (f32, f32)
+/// Returns unzoomed map pixel coordinates (x, y) of specified screenspace coordinates
+  pub fn screen_to_map(&self, x: i32, y: i32) -> (f32, f32)
+  {
+    ((self.position.x + x as f32) / self.zoomlevel,
+     (self.position.y + y as f32) / self.zoomlevel)
+  }
+
 /// Tile_To_Screen (&mut self, x: i32, y: i32) -> (i32, i32)
 /// Returns screenspace coordinates (x, y) of center of specified game tile
   pub fn tile_to_screen(&mut self, x: i32, y: i32) -> (i32, i32)
@@ -70,13 +85,13 @@ impl Camera {
     
     self.tsize = TILESIZE as f32 * self.zoomlevel;
 
-    let mut x: i32 = ((MAPSIZE_MAX_X as f32 * self.tsize) - self.tsize) as i32 - self.scrX;
-    let mut y: i32 = ((MAPSIZE_MAX_Y as f32 * self.tsize) - self.tsize) as i32 - self.scrY;
+    let mut x: i32 = ((self.mapwidth as f32 * self.tsize) - self.tsize) as i32 - self.scrX;
+    let mut y: i32 = ((self.mapheight as f32 * self.tsize) - self.tsize) as i32 - self.scrY;
 
     if x < 0 { x = 0; }
     if y < 0 { y = 0; }
-    self.maplimx = ((MAPSIZE_MAX_X as f32 * self.tsize) as i32 - self.scrX + (self.tsize) as i32);
-    self.maplimy = ((MAPSIZE_MAX_Y as f32 * self.tsize) as i32 - self.scrY + (self.tsize) as i32);
+    self.maplimx = ((self.mapwidth as f32 * self.tsize) as i32 - self.scrX + (self.tsize) as i32);
+    self.maplimy = ((self.mapheight as f32 * self.tsize) as i32 - self.scrY + (self.tsize) as i32);
 
     self.min_x = x;
     self.min_y = y;
@@ -84,6 +99,21 @@ impl Camera {
     true
   }
 
+  pub fn getzoomstep(&self) -> i32 { self.zoomstep }
+
+  /// Put the camera back at a saved position and zoom step
+  pub fn restore(&mut self, position: Point2, zoomstep: i32) {
+    let mut step = zoomstep;
+    if step < 0 { step = 0; }
+    if step >= ZOOMLEVELS as i32 { step = ZOOMLEVELS as i32 - 1; }
+    self.zoomstep = step;
+    self.zoomlevel = ZOOMSTEP[step as usize];
+    self.tsize = TILESIZE as f32 * self.zoomlevel;
+    self.maplimx = ((self.mapwidth as f32 * self.tsize) as i32 - self.scrX + (self.tsize) as i32);
+    self.maplimy = ((self.mapheight as f32 * self.tsize) as i32 - self.scrY + (self.tsize) as i32);
+    self.position = self.inbounds_point2(position);
+  }
+
   pub fn movestep(&mut self, x: f32, y: f32) -> bool {
     let p = Point2::new(self.position.x + (x * MOVESTEP ), self.position.y + (y * MOVESTEP ));
     self.position = self.inbounds_point2(p);
@@ -1,6 +1,17 @@
 //use ggez::graphics::Point2;
 //use super::{MAPSIZE_MAX_X, MAPSIZE_MAX_Y};
 //use super::camera;
+use rustworld_sim::jobs::JobKind;
+use rustworld_sim::items::{ItemKind, ITEMS};
+
+/// What a left click does
+#[derive(Copy, Clone, PartialEq)]
+pub enum Tool {
+  Select,
+  Build(JobKind), // Which construction job a click or drag plans
+  Zone,           // Drag out stockpiles, click one to give it the current filter
+  Cut,            // Mark the plants in a click or drag to be cut down
+}
 
 pub struct Input {
   pub mouse1down: bool,
@@ -13,6 +24,12 @@ pub struct Input {
 
   pub x: i32,
   pub y: i32,
+
+  pub tool: Tool,
+  pub dragstart: Option<(i32, i32)>, // Screen position where the current left drag began
+  pub dragtile: Option<(i32, i32)>,  // Map tile where the current build drag began
+  pub shift: bool,
+  pub zonekinds: Vec<ItemKind>, // What new stockpiles accept
 }
 
 impl Input {
@@ -28,7 +45,7 @@ impl Input {
     let mut x = 0;
     let mut y = 0;
 
-    Input { mouse1down, mouse2down, mouse3down, mwheeldelta, xdelta, ydelta, x, y }
+    Input { mouse1down, mouse2down, mouse3down, mwheeldelta, xdelta, ydelta, x, y, tool: Tool::Select, dragstart: None, dragtile: None, shift: false, zonekinds: ITEMS.to_vec() }
   }
 
   pub fn mousedown(&mut self, button: i32) {
@@ -1,40 +1,104 @@
 mod camera;
 mod input;
-mod map;
-mod entities;
+mod placement;
+mod render;
 
-use self::input::Input;
+use self::input::{Input, Tool};
 use self::camera::Camera;
-use self::map::Map;
-use self::entities::{ Entities, Entity, Actor, Wall };
+use rustworld_sim::TILESIZE;
+use rustworld_sim::clock::Clock;
+use rustworld_sim::error::SimError;
+use rustworld_sim::map::Pos;
+use rustworld_sim::jobs::JobKind;
+use rustworld_sim::items::{ItemKind, ITEMS};
+use rustworld_sim::plants::{Plant, PlantKind, PLANTS, STAGES};
+use rustworld_sim::needs::{Need, NEEDS};
+use rustworld_sim::entities::{ Entity, Buildable, BUILDING_NAMES };
+use rustworld_sim::save;
+use rustworld_sim::tiles::TileRegistry;
+use rustworld_sim::world::World;
 use ggez::graphics::Point2;
 use ggez::{graphics, Context, GameResult};
+use ggez::GameError::ResourceLoadError;
 use std::time::Duration;
-use ggez::event::{MouseButton, MouseState};
+use std::collections::HashMap;
+use ggez::event::{MouseButton, MouseState, Keycode, Mod};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
 
 use crate::states::{Assets, State, Transition};
 
-const MAPSIZE_MAX_X: i32 = 300;
-const MAPSIZE_MAX_Y: i32 = 300;
-const TILESIZE: i32 = 64; // side length of square pngs
+const COLONISTS: i32 = 3;
+const DRAG_THRESHOLD: i32 = 4; // pixels a left press can move and still count as a click
 
 pub struct PlayState {
   camera: Camera,
   input: Input,
-  map: Map,
-  entities: Entities,
+  world: World,
+  tilesprites: Vec<u32>, // Sprite id for each tile id in the registry
+  buildingsprites: Vec<u32>, // Sprite id for each building id in BUILDING_NAMES
+  itemsprites: HashMap<ItemKind, u32>,
+  plantsprites: HashMap<(PlantKind, u8), u32>, // By kind and stage
+  rally: Option<(i32, i32)>,
+  clock: Clock,
+}
 
+fn sim_error(e: SimError) -> ggez::GameError {
+  ResourceLoadError(e.to_string())
 }
 
 impl PlayState {
-  pub fn new(ctx: &mut Context, assets: &Assets) -> GameResult<Self> {
-    let mut map = Map::new(assets);
-    let mut camera = Camera::new(ctx);
-    let mut input = Input::new();
-    let mut entities = Entities::new();
-    let e = Actor::new(assets.get_id("lemmy".to_string()).unwrap(), MAPSIZE_MAX_X / &2, MAPSIZE_MAX_Y / &2, 1.0);
-    entities.add_actor(e);
-    Ok( PlayState { camera, input, map, entities } )
+  pub fn new(ctx: &mut Context, assets: &Assets, seed: u32, width: i32, height: i32) -> GameResult<Self> {
+    let tiles = TileRegistry::standard();
+    let mut tilesprites = Vec::new();
+    for (_, t) in tiles.iter() {
+      tilesprites.push(assets.get_id(t.name.clone())?);
+    }
+    let mut buildingsprites = Vec::new();
+    for name in BUILDING_NAMES.iter() {
+      buildingsprites.push(assets.get_id(name.to_string())?);
+    }
+    let mut itemsprites = HashMap::new();
+    for kind in ITEMS.iter() {
+      itemsprites.insert(*kind, assets.get_id(kind.spritename().to_string())?);
+    }
+    let mut plantsprites = HashMap::new();
+    for kind in PLANTS.iter() {
+      for stage in 0..STAGES {
+        plantsprites.insert((*kind, stage), assets.get_id(Plant::spritename(*kind, stage))?);
+      }
+    }
+    let sprite = assets.get_id("lemmy".to_string())?;
+    let world = World::new(tiles, seed, width, height, COLONISTS, sprite);
+    let (w, h) = (ctx.conf.window_mode.width as i32, ctx.conf.window_mode.height as i32);
+    let camera = Camera::new(w, h, world.map.width, world.map.height);
+    let input = Input::new();
+    Ok( PlayState { camera, input, world, tilesprites, buildingsprites, itemsprites, plantsprites, rally: None, clock: Clock::new() } )
+  }
+
+  pub fn save(&self, path: &str) -> GameResult<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let camera = (self.camera.position.x, self.camera.position.y);
+    save::save_game(&mut out, &self.world, camera, self.camera.getzoomstep()).map_err(sim_error)
+  }
+
+  pub fn load(&mut self, ctx: &mut Context, path: &str) -> GameResult<()> {
+    let tiles = TileRegistry::standard();
+    let s = save::load_game(BufReader::new(File::open(path)?), &tiles).map_err(sim_error)?;
+    let (w, h) = (ctx.conf.window_mode.width as i32, ctx.conf.window_mode.height as i32);
+    self.camera = Camera::new(w, h, s.map.width, s.map.height);
+    self.camera.restore(Point2::new(s.camera.0, s.camera.1), s.zoomstep);
+    self.world = World::from_save(tiles, s);
+    self.rally = None;
+    Ok(())
+  }
+
+  fn toggle_zonekind(&mut self, kind: ItemKind) {
+    let kinds = &mut self.input.zonekinds;
+    match kinds.iter().position(|k| *k == kind) {
+      Some(i) => { kinds.remove(i); }
+      None => { kinds.push(kind); kinds.sort(); }
+    }
   }
 
   pub fn scr_to_map(&self, x: i32, y: i32) -> (i32, i32) {
@@ -69,8 +133,10 @@ impl PlayState {
 }
 
 impl State for PlayState {
-  fn update(&mut self, ctx: &mut Context, assets: &Assets, dt: Duration,) -> GameResult<Transition> {
-    self.entities.update(ggez::timer::get_delta(ctx).subsec_millis(), self.camera.tsize);
+  fn update(&mut self, _ctx: &mut Context, assets: &Assets, dt: Duration,) -> GameResult<Transition> {
+    for _ in 0..self.clock.advance(dt) {
+      self.world.tick().map_err(sim_error)?;
+    }
     Ok(Transition::None)
   }
 
@@ -83,12 +149,12 @@ impl State for PlayState {
     let mut xdrawmin = ((camx / tsize) - 1.0) as i32;
     if xdrawmin < 0 { xdrawmin = 0; }
     let mut xdrawmax = ((camx / tsize) + 1.0 + (ctx.conf.window_mode.width as f32 / tsize)) as i32;
-    if xdrawmax >= MAPSIZE_MAX_X { xdrawmax = MAPSIZE_MAX_X ; }
+    if xdrawmax >= self.world.map.width { xdrawmax = self.world.map.width ; }
 
     let mut ydrawmin = ((camy / tsize) - 1.0) as i32;
     if ydrawmin < 0 { ydrawmin = 0; }
     let mut ydrawmax = ((camy / tsize) + 1.0 + (ctx.conf.window_mode.height as f32 / tsize)) as i32;
-    if ydrawmax >= MAPSIZE_MAX_Y { ydrawmax = MAPSIZE_MAX_Y ; }
+    if ydrawmax >= self.world.map.height { ydrawmax = self.world.map.height ; }
 
     for x in xdrawmin..xdrawmax {
       for y in ydrawmin..ydrawmax {
@@ -100,19 +166,137 @@ impl State for PlayState {
           scale: scale,// * 1.1,
           ..Default::default()
         };
-        match self.map.tilemap.get((x + (y * MAPSIZE_MAX_X)) as usize) {
+        match self.world.map.tilemap.get(self.world.map.getmapvecidx(x, y)) {
           Some((i, 0)) => {
-            assets.draw_image(&i.id, p);
+            assets.draw_image(&self.tilesprites[i.id as usize], p);
           }
           Some((i, x)) => {
-            assets.draw_alt_image(&i.id, (x - 1) as usize, p);
-
+            let sprite = self.tilesprites[i.id as usize];
+            if assets.get_alt_count(&sprite) >= *x as usize {
+              assets.draw_alt_image(&sprite, (x - 1) as usize, p);
+            } else {
+              assets.draw_image(&sprite, p); // No transition sprite for this edge mask
+            }
           }
           _ => {},
         }
+        if let Some(sprite) = self.world.map.get_plant_at(x, y).and_then(|p| self.plantsprites.get(&(p.kind, p.stage))) {
+          let p = graphics::DrawParam {
+            dest: Point2::new(((x * TILESIZE) as f32 * scale.x) - camx, ((y * TILESIZE) as f32 * scale.y) - camy),
+            scale: scale,
+            ..Default::default()
+          };
+          assets.draw_plant_image(sprite, p);
+        }
+      }
+    }
+    render::draw_items(&self.world.map, &self.itemsprites, camx, camy, scale, assets);
+    render::draw_entities(&self.world.entities, &self.buildingsprites, camx as i32, camy as i32, scale, assets, self.clock.alpha());
+    render::draw_jobs(&self.world.jobs, camx, camy, scale, assets);
+
+    if let (Tool::Select, Some((x1, y1))) = (self.input.tool, self.input.dragstart) {
+      let (x1, y1) = (x1 as f32, y1 as f32);
+      let (x2, y2) = (self.input.x as f32, self.input.y as f32);
+      assets.draw_UI_line(((x1, y1), (x2, y1)));
+      assets.draw_UI_line(((x2, y1), (x2, y2)));
+      assets.draw_UI_line(((x2, y2), (x1, y2)));
+      assets.draw_UI_line(((x1, y2), (x1, y1)));
+    }
+
+    if self.input.tool == Tool::Zone || self.input.tool == Tool::Cut {
+      if let Some(start) = self.input.dragtile {
+        let end = self.camera.mouse_to_tile(self.input.x, self.input.y);
+        let x1 = ((start.0.min(end.0) * TILESIZE) as f32 * scale.x) - camx;
+        let y1 = ((start.1.min(end.1) * TILESIZE) as f32 * scale.y) - camy;
+        let x2 = (((start.0.max(end.0) + 1) * TILESIZE) as f32 * scale.x) - camx;
+        let y2 = (((start.1.max(end.1) + 1) * TILESIZE) as f32 * scale.y) - camy;
+        assets.draw_UI_line(((x1, y1), (x2, y1)));
+        assets.draw_UI_line(((x2, y1), (x2, y2)));
+        assets.draw_UI_line(((x2, y2), (x1, y2)));
+        assets.draw_UI_line(((x1, y2), (x1, y1)));
+      }
+    }
+    if self.input.tool == Tool::Zone {
+      render::draw_zones(&self.world.stockpiles, camx, camy, scale, assets);
+      let kinds: Vec<&str> = self.input.zonekinds.iter().map(|k| k.name()).collect();
+      let hint = format!("Stockpile accepts: {} (4 wood, 5 stone, 6 food, delete removes)",
+                         if kinds.is_empty() { "nothing".to_string() } else { kinds.join(", ") });
+      assets.draw_UI_text(&hint, (10.0, 30.0), graphics::Color::new(1.0, 1.0, 1.0, 1.0));
+    }
+
+    let speed = match self.clock.getspeed() {
+      0 => "Paused (space to resume, . to step)".to_string(),
+      n => format!("Speed {}x", n),
+    };
+    assets.draw_UI_text(&speed, (10.0, 10.0), graphics::Color::new(1.0, 1.0, 1.0, 1.0));
+
+    if let Some((x, y)) = self.rally {
+      // Flag post on the rally tile
+      let x1 = (((x * TILESIZE) + (TILESIZE / 2)) as f32 * scale.x) - camx;
+      let y1 = ((y * TILESIZE) as f32 * scale.y) - camy;
+      let y2 = y1 + TILESIZE as f32 * scale.y;
+      let w = (TILESIZE / 3) as f32 * scale.x;
+      assets.draw_UI_line(((x1, y2), (x1, y1)));
+      assets.draw_UI_line(((x1, y1), (x1 + w, y1 + (w / 2.0))));
+      assets.draw_UI_line(((x1 + w, y1 + (w / 2.0)), (x1, y1 + w)));
+    }
+
+    if let (Tool::Build(kind), Some(start)) = (self.input.tool, self.input.dragtile) {
+      let end = self.camera.mouse_to_tile(self.input.x, self.input.y);
+      let mut tiles = placement::drag_tiles(start, end, self.input.shift);
+      tiles.sort();
+      tiles.dedup();
+      let mut valid = 0;
+      for (x, y) in tiles.iter() {
+        let ok = self.world.jobs.canplace(kind, *x, *y, &mut self.world.map);
+        if ok { valid = valid + 1; }
+        let p = graphics::DrawParam {
+          dest: Point2::new(((x * TILESIZE) as f32 * scale.x) - camx, ((y * TILESIZE) as f32 * scale.y) - camy),
+          scale: scale,
+          color: Some(if ok { graphics::Color::new(1.0, 1.0, 1.0, 0.5) } else { graphics::Color::new(1.0, 0.2, 0.2, 0.6) }),
+          ..Default::default()
+        };
+        if let Some(sprite) = kind.sprite().and_then(|s| self.buildingsprites.get(s as usize)) {
+          assets.draw_building_image(sprite, p);
+        }
+      }
+      let blocked = tiles.len() - valid;
+      let cost = match kind.cost() {
+        Some(c) => format!(", {} {}", c.count * valid as u32, c.kind.name()),
+        None => String::new(),
+      };
+      let (text, color) = if blocked > 0 {
+        (format!("{} ({} blocked){}", valid, blocked, cost), graphics::Color::new(1.0, 0.4, 0.4, 1.0))
+      } else {
+        (format!("{}{}", valid, cost), graphics::Color::new(1.0, 1.0, 1.0, 1.0))
+      };
+      assets.draw_UI_text(&text, ((self.input.x + 20) as f32, (self.input.y + 20) as f32), color);
+    }
+
+    // Inspect panel for a single selected colonist
+    let selected: Vec<_> = self.world.entities.getactors().iter().filter(|a| a.selected).collect();
+    if selected.len() == 1 {
+      let a = selected[0];
+      let mut lines = vec![format!("Colonist {}  health {:.0}%", a.getentityid(), a.health * 100.0)];
+      for n in NEEDS.iter() {
+        lines.push(format!("{} {:.0}%", n.name(), a.needs.get(*n) * 100.0));
+      }
+      let mut status = match a.getactivity() {
+        Some((n, t)) if t == a.getoccupiedtile() && !a.ismoving() => n.verb().to_string(),
+        Some((Need::Hunger, _)) => "looking for food".to_string(),
+        Some((Need::Rest, _)) => "looking for a bed".to_string(),
+        Some((Need::Joy, _)) => "looking for something to do".to_string(),
+        None => a.getbehaviour().map_or("idle", |b| b.verb()).to_string(),
+      };
+      if a.needs.isstarving() { status.push_str(" (starving)"); }
+      if a.needs.isbroken() { status.push_str(" (broken)"); }
+      lines.push(status);
+      let bottom = ctx.conf.window_mode.height as f32 - 10.0;
+      for (i, l) in lines.iter().enumerate() {
+        let y = bottom - ((lines.len() - i) as f32 * 20.0);
+        assets.draw_UI_text(l, (10.0, y), graphics::Color::new(1.0, 1.0, 1.0, 1.0));
       }
     }
-    self.entities.draw(camx as i32, camy as i32, scale, assets);
 
     Ok(())
   }
@@ -121,20 +305,29 @@ impl State for PlayState {
     match button {
       MouseButton::Left => {
         self.input.mousedown(1);
-        let (a, b) = self.camera.mouse_to_tile(x, y);
-        if self.map.get_building_at(a, b) {
-          self.map.clear_building_at(a, b, &mut self.entities); 
-        } else {
-          let mut  w = Wall::new(0, a, b, 1.0, self.entities.getindex());
-
-          self.map.set_building_at(a, b, w, &mut self.entities);
+        match self.input.tool {
+          Tool::Select => { self.input.dragstart = Some((x, y)); }
+          Tool::Build(_) | Tool::Zone | Tool::Cut => { self.input.dragtile = Some(self.camera.mouse_to_tile(x, y)); }
         }
-
       }
       MouseButton::Right => {
         self.input.mousedown(2);
         let (a, b) = self.camera.mouse_to_tile(x, y);
-        self.entities.get_actor().setmovetarget(a, b, &mut self.camera, &mut self.map);
+        if self.world.entities.get_selected().count() > 1 {
+          // Groups share one flow field instead of each searching for a path
+          let field = self.world.flows.get(&Pos(a, b), &self.world.map);
+          for actor in self.world.entities.get_selected() {
+            self.world.jobs.release_actor(actor.getentityid());
+            actor.setbehaviour(None);
+            actor.follow(field, &self.world.map);
+          }
+        } else {
+          for actor in self.world.entities.get_selected() {
+            self.world.jobs.release_actor(actor.getentityid());
+            actor.setbehaviour(None);
+            actor.setmovetarget(a, b, &self.world.map, &mut self.world.paths);
+          }
+        }
       }
       MouseButton::Middle => self.input.mousedown(3),
       _ => {},
@@ -144,7 +337,57 @@ impl State for PlayState {
 
   fn mouse_button_up_event(&mut self, ctx: &mut Context, button: MouseButton, x: i32, y: i32) {
     match button {
-      MouseButton::Left => self.input.mouseup(1),
+      MouseButton::Left => {
+        self.input.mouseup(1);
+        if let (Tool::Select, Some((x1, y1))) = (self.input.tool, self.input.dragstart.take()) {
+          let (mx, my) = self.camera.screen_to_map(x, y);
+          if (x - x1).abs() <= DRAG_THRESHOLD && (y - y1).abs() <= DRAG_THRESHOLD {
+            self.world.entities.select_at(mx, my);
+          } else {
+            let (sx, sy) = self.camera.screen_to_map(x1, y1);
+            self.world.entities.select_in(sx, sy, mx, my);
+          }
+        }
+        if let (Tool::Build(kind), Some(start)) = (self.input.tool, self.input.dragtile.take()) {
+          let end = self.camera.mouse_to_tile(x, y);
+          if start == end {
+            let (a, b) = end;
+            if self.world.jobs.cancel_plan_at(a, b, &mut self.world.entities, &mut self.world.map) {
+              // Clicking a blueprint or queued job takes it back
+            } else if self.world.map.get_building_at(a, b) {
+              self.world.jobs.add(JobKind::Demolish, a, b);
+            } else {
+              self.world.jobs.place_blueprint(kind, a, b, &mut self.world.entities, &mut self.world.map);
+            }
+          } else {
+            for (a, b) in placement::drag_tiles(start, end, self.input.shift) {
+              self.world.jobs.place_blueprint(kind, a, b, &mut self.world.entities, &mut self.world.map);
+            }
+          }
+        }
+        if let (Tool::Cut, Some(start)) = (self.input.tool, self.input.dragtile.take()) {
+          let end = self.camera.mouse_to_tile(x, y);
+          let marked = self.world.jobs.get_at(end.0, end.1).map_or(false, |j| j.kind == JobKind::Cut);
+          if start == end && marked {
+            self.world.jobs.cancel_at(end.0, end.1, &mut self.world.entities);
+          } else {
+            for (a, b) in placement::area_tiles(start, end) {
+              if self.world.map.get_plant_at(a, b).is_some() { self.world.jobs.add(JobKind::Cut, a, b); }
+            }
+          }
+        }
+        if let (Tool::Zone, Some(start)) = (self.input.tool, self.input.dragtile.take()) {
+          let end = self.camera.mouse_to_tile(x, y);
+          let kinds = self.input.zonekinds.clone();
+          let existing = self.world.stockpiles.zone_at(end.0, end.1).map(|z| z.id);
+          match existing {
+            Some(id) if start == end => self.world.stockpiles.set_accepts(id, kinds),
+            _ => if self.world.stockpiles.add(&placement::area_tiles(start, end), kinds, &self.world.map).is_none() {
+              println!("Can't place a stockpile there: every tile is blocked or already in one");
+            },
+          }
+        }
+      }
       MouseButton::Right => self.input.mouseup(2),
       MouseButton::Middle => self.input.mouseup(3),
       _ => {},
@@ -159,6 +402,94 @@ impl State for PlayState {
     self.input.setpos(x, y);
   }
   
+  fn key_down_event(&mut self, ctx: &mut Context, keycode: Keycode, _keymod: Mod, repeat: bool) {
+    if repeat { return; }
+    match keycode {
+      Keycode::F5 => {
+        match self.save(save::QUICKSAVE_PATH) {
+          Ok(_) => println!("Saved to {}", save::QUICKSAVE_PATH),
+          Err(e) => println!("Save failed: {}", e),
+        }
+      }
+      Keycode::B | Keycode::S | Keycode::D | Keycode::E | Keycode::T => {
+        let kind = match keycode {
+          Keycode::B => JobKind::BuildWall,
+          Keycode::S => JobKind::BuildStoneWall,
+          Keycode::D => JobKind::BuildDoor,
+          Keycode::E => JobKind::BuildBed,
+          _ => JobKind::BuildTable,
+        };
+        self.input.tool = if self.input.tool == Tool::Build(kind) { Tool::Select } else { Tool::Build(kind) };
+        self.input.dragstart = None;
+        self.input.dragtile = None;
+      }
+      Keycode::C => {
+        self.input.tool = if self.input.tool == Tool::Cut { Tool::Select } else { Tool::Cut };
+        self.input.dragstart = None;
+        self.input.dragtile = None;
+      }
+      Keycode::Z => {
+        self.input.tool = if self.input.tool == Tool::Zone { Tool::Select } else { Tool::Zone };
+        self.input.dragstart = None;
+        self.input.dragtile = None;
+      }
+      Keycode::Num4 => { self.toggle_zonekind(ItemKind::Wood); }
+      Keycode::Num5 => { self.toggle_zonekind(ItemKind::Stone); }
+      Keycode::Num6 => { self.toggle_zonekind(ItemKind::Food); }
+      Keycode::Delete => {
+        let (a, b) = self.camera.mouse_to_tile(self.input.x, self.input.y);
+        if let Some(id) = self.world.stockpiles.zone_at(a, b).map(|z| z.id) {
+          self.world.stockpiles.remove(id);
+        }
+      }
+      Keycode::Space => { self.clock.toggle_pause(); }
+      Keycode::Num1 => { self.clock.setspeed(1); }
+      Keycode::Num2 => { self.clock.setspeed(2); }
+      Keycode::Num3 => { self.clock.setspeed(3); }
+      Keycode::Period => { self.clock.step(); }
+      Keycode::R => {
+        // Rally point: everyone drops what they're doing and heads for the tile under the cursor
+        let (a, b) = self.camera.mouse_to_tile(self.input.x, self.input.y);
+        if self.world.map.ispassable(a, b) {
+          self.rally = Some((a, b));
+          let field = self.world.flows.get(&Pos(a, b), &self.world.map);
+          for actor in self.world.entities.getactors_mut().iter_mut() {
+            self.world.jobs.release_actor(actor.getentityid());
+            actor.setbehaviour(None);
+            actor.follow(field, &self.world.map);
+          }
+        }
+      }
+      Keycode::L => {
+        // Toggle the lock on the door under the cursor
+        let (a, b) = self.camera.mouse_to_tile(self.input.x, self.input.y);
+        let locked = self.world.map.get_building(a, b).map_or(false, |d| d.islocked());
+        self.world.map.set_locked_at(a, b, !locked, &mut self.world.entities);
+      }
+      Keycode::LShift | Keycode::RShift => { self.input.shift = true; }
+      Keycode::Escape => {
+        self.input.tool = Tool::Select;
+        self.input.dragstart = None;
+        self.input.dragtile = None;
+        self.world.entities.clear_selection();
+      }
+      Keycode::F9 => {
+        match self.load(ctx, save::QUICKSAVE_PATH) {
+          Ok(_) => println!("Loaded {}", save::QUICKSAVE_PATH),
+          Err(e) => println!("Load failed: {}", e),
+        }
+      }
+      _ => {}
+    }
+  }
+
+  fn key_up_event(&mut self, _ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
+    match keycode {
+      Keycode::LShift | Keycode::RShift => { self.input.shift = false; }
+      _ => {}
+    }
+  }
+
   fn mouse_wheel_event(&mut self, ctx: &mut Context, _x: i32, y: i32) {
     let p = Point2::new(self.input.x as f32, self.input.y as f32);
     self.camera.zoom(y, p);
@@ -0,0 +1,57 @@
+use bresenham::Bresenham;
+
+/// Tiles covered by a build drag from tile `from` to tile `to`: a straight run of walls,
+/// or with `room` set the hollow outline of the rectangle they span
+pub fn drag_tiles(from: (i32, i32), to: (i32, i32), room: bool) -> Vec<(i32, i32)> {
+  let mut tiles = Vec::new();
+  if room {
+    let (minx, maxx) = (from.0.min(to.0), from.0.max(to.0));
+    let (miny, maxy) = (from.1.min(to.1), from.1.max(to.1));
+    for x in minx..=maxx {
+      tiles.push((x, miny));
+      if maxy != miny { tiles.push((x, maxy)); }
+    }
+    for y in (miny + 1)..maxy {
+      tiles.push((minx, y));
+      if maxx != minx { tiles.push((maxx, y)); }
+    }
+  } else {
+    for (x, y) in Bresenham::new((from.0 as isize, from.1 as isize), (to.0 as isize, to.1 as isize)) {
+      tiles.push((x as i32, y as i32));
+    }
+    tiles.push(to); // Bresenham stops short of the end point
+  }
+  tiles
+}
+
+/// Every tile in the rectangle spanned by tiles `from` and `to`
+pub fn area_tiles(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+  let mut tiles = Vec::new();
+  for y in from.1.min(to.1)..=from.1.max(to.1) {
+    for x in from.0.min(to.0)..=from.0.max(to.0) {
+      tiles.push((x, y));
+    }
+  }
+  tiles
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_drag_tiles() {
+    assert_eq!(drag_tiles((2, 2), (2, 2), false), vec![(2, 2)]);
+    assert_eq!(drag_tiles((0, 0), (3, 0), false), vec![(0, 0), (1, 0), (2, 0), (3, 0)]);
+
+    let mut room = drag_tiles((4, 4), (1, 1), true);
+    room.sort();
+    room.dedup();
+    assert_eq!(room.len(), 12); // 4x4 outline
+    assert!(!room.contains(&(2, 2)));
+    assert_eq!(drag_tiles((1, 1), (1, 3), true).len(), 3);
+
+    assert_eq!(area_tiles((3, 2), (1, 1)).len(), 6);
+    assert_eq!(area_tiles((5, 5), (5, 5)), vec![(5, 5)]);
+  }
+}
@@ -0,0 +1,128 @@
+use super::TILESIZE;
+use crate::states::Assets;
+use ggez::graphics::{Color, DrawParam, Point2, WHITE};
+use rustworld_sim::entities::{Entities, Entity, Buildable};
+use rustworld_sim::jobs::{Jobs, JobKind};
+use rustworld_sim::map::Map;
+use rustworld_sim::items::{ItemKind, Stockpiles};
+use std::collections::HashMap;
+
+fn drawparams<E: Entity + ?Sized>(e: &E, camx: f32, camy: f32, scale: Point2) -> DrawParam {
+  let (x, y) = e.getposition();
+  DrawParam {
+    dest: Point2::new(-camx as f32 + (x * scale.x ), -camy as f32 + (y * scale.y )),
+    scale: scale,
+    rotation: e.getrotation(),
+    ..Default::default()
+  }
+}
+
+/// alpha is how far the clock is between the last tick and the next, 0...1. buildingsprites maps
+/// building ids to sprite ids
+pub fn draw_entities(entities: &Entities, buildingsprites: &[u32], camx: i32, camy: i32, scale: Point2, assets: &mut Assets, alpha: f32) {
+  let lineoffsetx = (TILESIZE / 2) as f32 * scale.x;
+  let lineoffsety = (TILESIZE / 2) as f32 * scale.y;
+  for v in entities.gettiles().iter() {
+    let p = drawparams(v, camx as f32, camy as f32, scale);
+    assets.draw_image(&v.id, p);
+  }
+  for (_, b) in entities.getbuildings().iter() {
+    let sprite = match buildingsprites.get(b.getid() as usize) {
+      Some(s) => s,
+      None => continue,
+    };
+    let mut p = drawparams(b.as_ref(), camx as f32, camy as f32, scale);
+    if b.isblueprint() {
+      p.color = Some(Color::new(0.6, 0.8, 1.0, 0.3 + (0.5 * b.getprogress())));
+    } else if b.islocked() {
+      p.color = Some(Color::new(1.0, 0.5, 0.5, 1.0));
+    }
+    match b.getalternate() {
+      Some(alt) => assets.draw_building_alt_image(sprite, alt, p),
+      None => assets.draw_building_image(sprite, p),
+    }
+  }
+  for v in entities.getactors().iter() {
+    let (x, y) = v.getdrawposition(alpha);
+    let p = DrawParam {
+      dest: Point2::new(-camx as f32 + (x * scale.x), -camy as f32 + (y * scale.y)),
+      scale: scale,
+      ..Default::default()
+    };
+    if v.ismoving() {
+      let mut p1 = (((x * scale.x) - camx as f32) + lineoffsetx,
+                    ((y * scale.y) - camy as f32) + lineoffsety);
+      for s in v.getsteps().iter() {
+        let p2 = ((((s.0 * TILESIZE ) as f32 * scale.x) - camx as f32) + lineoffsetx, (((s.1 * TILESIZE) as f32 * scale.y) - camy as f32) + lineoffsety);
+        assets.draw_UI_line((p1, p2));
+        p1 = p2;
+      }
+    }
+    if v.selected {
+      let x1 = (x * scale.x) - camx as f32;
+      let y1 = (y * scale.y) - camy as f32;
+      let x2 = x1 + TILESIZE as f32 * scale.x;
+      let y2 = y1 + TILESIZE as f32 * scale.y;
+      assets.draw_UI_line(((x1, y1), (x2, y1)));
+      assets.draw_UI_line(((x2, y1), (x2, y2)));
+      assets.draw_UI_line(((x2, y2), (x1, y2)));
+      assets.draw_UI_line(((x1, y2), (x1, y1)));
+    }
+    assets.draw_actor_image(&v.getid(), p);
+  }
+}
+
+/// Item stacks lying on the map, with their count in the corner
+pub fn draw_items(map: &Map, itemsprites: &HashMap<ItemKind, u32>, camx: f32, camy: f32, scale: Point2, assets: &mut Assets) {
+  for (&(x, y), s) in map.getitems().iter() {
+    let sx = ((x * TILESIZE) as f32 * scale.x) - camx;
+    let sy = ((y * TILESIZE) as f32 * scale.y) - camy;
+    let p = DrawParam {
+      dest: Point2::new(sx, sy),
+      scale: scale,
+      ..Default::default()
+    };
+    if let Some(sprite) = itemsprites.get(&s.kind) {
+      assets.draw_item_image(sprite, p);
+    }
+    if s.count > 1 {
+      assets.draw_UI_text(&s.count.to_string(), (sx + 2.0, sy + (TILESIZE / 2) as f32 * scale.y), WHITE);
+    }
+  }
+}
+
+/// Outline every stockpile tile
+pub fn draw_zones(stockpiles: &Stockpiles, camx: f32, camy: f32, scale: Point2, assets: &mut Assets) {
+  for z in stockpiles.getzones().iter() {
+    for &(x, y) in z.tiles.iter() {
+      let x1 = ((x * TILESIZE) as f32 * scale.x) - camx;
+      let y1 = ((y * TILESIZE) as f32 * scale.y) - camy;
+      let x2 = x1 + TILESIZE as f32 * scale.x;
+      let y2 = y1 + TILESIZE as f32 * scale.y;
+      // Only edges that border a tile outside the zone, so a stockpile reads as one area
+      if !z.tiles.contains(&(x, y - 1)) { assets.draw_UI_line(((x1, y1), (x2, y1))); }
+      if !z.tiles.contains(&(x + 1, y)) { assets.draw_UI_line(((x2, y1), (x2, y2))); }
+      if !z.tiles.contains(&(x, y + 1)) { assets.draw_UI_line(((x2, y2), (x1, y2))); }
+      if !z.tiles.contains(&(x - 1, y)) { assets.draw_UI_line(((x1, y2), (x1, y1))); }
+    }
+  }
+}
+
+/// Outline queued jobs: a box for building, a crossed box for demolishing
+pub fn draw_jobs(jobs: &Jobs, camx: f32, camy: f32, scale: Point2, assets: &mut Assets) {
+  // Blueprints are drawn as buildings, hauls as the items they move
+  for j in jobs.getjobs().iter().filter(|j| j.kind.sprite().is_none() && j.kind != JobKind::Haul) {
+    let x1 = ((j.target.0 * TILESIZE) as f32 * scale.x) - camx;
+    let y1 = ((j.target.1 * TILESIZE) as f32 * scale.y) - camy;
+    let x2 = x1 + TILESIZE as f32 * scale.x;
+    let y2 = y1 + TILESIZE as f32 * scale.y;
+    assets.draw_UI_line(((x1, y1), (x2, y1)));
+    assets.draw_UI_line(((x2, y1), (x2, y2)));
+    assets.draw_UI_line(((x2, y2), (x1, y2)));
+    assets.draw_UI_line(((x1, y2), (x1, y1)));
+    if j.kind == JobKind::Demolish {
+      assets.draw_UI_line(((x1, y1), (x2, y2)));
+      assets.draw_UI_line(((x2, y1), (x1, y2)));
+    }
+  }
+}